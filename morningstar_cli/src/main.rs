@@ -1,87 +1,658 @@
 use chrono::prelude::*;
 use clap::Parser;
+use morningstar_model::round_trip::Stay;
+use morningstar_model::{DepartureFilter, LocationType, Stop, StopId, StopSelector, TimeTable};
+
+/// Departures looked at to tell where buses leaving from a quay go.
+const QUAY_LABEL_DEPARTURES: usize = 10;
 
 #[derive(Parser)]
 struct Opt {
-    depart_from: Option<String>,
-    // go_to: Option<String>,
-    number_to_show: Option<usize>,
+    /// Shows the next departures from a stop when left out.
+    #[command(subcommand)]
+    command: Option<Command>,
 
     #[arg(short, long)]
     file: std::path::PathBuf,
 
-    #[arg(short, long)]
+    /// Only show departures of this route id.
+    #[arg(short, long, global = true)]
+    route: Option<String>,
+
+    #[arg(short, long, global = true)]
     verbose: bool,
 }
 
+#[derive(clap::Args, Default)]
+struct BoardArgs {
+    /// Stop to show departures from, asked for when left out.
+    depart_from: Option<String>,
+    // go_to: Option<String>,
+    number_to_show: Option<usize>,
+
+    /// Stop id of a single quay, or of a stop area, to show departures from rather than asking
+    /// for a stop name.
+    #[arg(long)]
+    quay: Option<String>,
+
+    /// Only show departures going this way along the line.
+    #[arg(long, value_enum)]
+    direction: Option<DirectionArg>,
+
+    /// Only show departures ending at this stop or showing this destination.
+    #[arg(long)]
+    towards: Option<String>,
+
+    /// Only show departures calling at this stop later on, wherever they end.
+    #[arg(long)]
+    via: Option<String>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Shows the next departures from a stop, quay by quay.
+    Departures(BoardArgs),
+    /// Lists ways to go to a stop and come back on the same day.
+    RoundTrip {
+        from: String,
+        to: String,
+        /// Minutes to spend at the destination at least.
+        #[arg(long, default_value_t = 0, conflicts_with = "stay")]
+        min_stay: i64,
+        /// Minutes one would like to spend at the destination, coming back on the bus closest
+        /// to that.
+        #[arg(long)]
+        stay: Option<i64>,
+        /// Day of the trip as YYYY-MM-DD, today when left out.
+        #[arg(long)]
+        date: Option<NaiveDate>,
+    },
+    /// Writes a printable grid timetable of departures from a stop.
+    Print {
+        stop: String,
+        /// Only departures going this way along the line.
+        #[arg(long, value_enum)]
+        direction: Option<DirectionArg>,
+        /// Day as YYYY-MM-DD in the week to take departures from, this week when left out.
+        #[arg(long)]
+        week_of: Option<NaiveDate>,
+        /// Write an SVG image rather than an HTML page.
+        #[arg(long)]
+        svg: bool,
+        /// File to write to, standard output when left out.
+        #[arg(short, long)]
+        out: Option<std::path::PathBuf>,
+    },
+    /// Writes an iCalendar file of trips from a stop, to import in a calendar app.
+    Calendar {
+        from: String,
+        /// Only trips going on to this stop.
+        #[arg(long)]
+        to: Option<String>,
+        /// First day as YYYY-MM-DD, today when left out.
+        #[arg(long)]
+        start: Option<NaiveDate>,
+        /// Number of days to cover.
+        #[arg(long, default_value_t = 7)]
+        days: u64,
+        /// File to write to, standard output when left out.
+        #[arg(short, long)]
+        out: Option<std::path::PathBuf>,
+    },
+    /// Tells which services run on school days and which during school holidays.
+    Services,
+    /// Reports how long the schedule says going from a stop to another takes, by hour of
+    /// departure and type of day.
+    RunTimes {
+        from: String,
+        to: String,
+        /// First day as YYYY-MM-DD, today when left out.
+        #[arg(long)]
+        start: Option<NaiveDate>,
+        /// Number of days to take journeys from.
+        #[arg(long, default_value_t = 28)]
+        days: u64,
+    },
+    /// Tabulates the service at a stop by type of day: first and last departures, departures
+    /// per hour and the longest gap between two of them.
+    Stats {
+        stop: String,
+        /// Day as YYYY-MM-DD in the week to take figures from, this week when left out.
+        #[arg(long)]
+        week_of: Option<NaiveDate>,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum DirectionArg {
+    Outbound,
+    Inbound,
+}
+
+impl From<DirectionArg> for morningstar_model::Direction {
+    fn from(direction: DirectionArg) -> Self {
+        match direction {
+            DirectionArg::Outbound => Self::Outbound,
+            DirectionArg::Inbound => Self::Inbound,
+        }
+    }
+}
+
 pub fn main() {
     let opt = Opt::parse();
 
-    let (today, now) = {
-        let now = Local::now();
-        let now = now.naive_local();
-        (now.date(), now.time())
-    };
+    let now = Local::now().fixed_offset();
 
     let tt = {
-        let file = std::fs::File::open(&opt.file).unwrap();
-        let mut tt: morningstar_model::TimeTable = ron::de::from_reader(file).unwrap();
+        let mut tt = match morningstar_model::TimeTable::load(&opt.file) {
+            Ok(tt) => tt,
+            Err(err) => {
+                eprintln!("{}: {err}", opt.file.display());
+                return;
+            }
+        };
         tt.sort_journeys_and_stops();
         tt
     };
     if opt.verbose {
         println!(
-            "source file or url: {}\ncreated on: {}\nline id: {}",
+            "source file or url: {}\ncreated on: {}\nline ids: {}",
             tt.extracted_from,
-            tt.extracted_on.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
-            tt.extracted_line_id
+            tt.extracted_on.format("%Y-%m-%d %H:%M:%S UTC"),
+            tt.extracted_route_ids.join(", ")
         );
     }
-    let stops_served_today: Vec<_> = tt.get_stops_served_on_day(&today).iter().copied().collect();
-    if stops_served_today.is_empty() {
-        eprintln!("No stops served today");
+    let route_id = opt.route.as_deref();
+    let today = now.with_timezone(&tt.tz()).date_naive();
+    if opt.verbose {
+        if let Some(window) = tt.get_validity_window() {
+            println!("valid from {} to {}", window.start(), window.end());
+        }
     }
-    let Some(depart_from) = get_departure_stop(&opt, stops_served_today) else {
-        eprintln!("failed to ask or match stop name");
-        return;
+    if let Some(last_date) = tt.expires_within(today, morningstar_model::EXPIRY_WARNING_DAYS) {
+        if last_date < today {
+            eprintln!("The timetable expired on {last_date}, it needs updating");
+        } else {
+            eprintln!("The timetable expires on {last_date}, it needs updating soon");
+        }
+    }
+    let default_board = BoardArgs::default();
+    let board = match &opt.command {
+        Some(Command::Departures(board)) => board,
+        None => &default_board,
+        Some(Command::RoundTrip {
+            from,
+            to,
+            min_stay,
+            stay,
+            date,
+        }) => {
+            let stay = match stay {
+                Some(stay) => Stay::About(chrono::Duration::minutes(*stay)),
+                None => Stay::AtLeast(chrono::Duration::minutes(*min_stay)),
+            };
+            display_round_trips(&tt, from, to, date.unwrap_or(today), stay, route_id);
+            return;
+        }
+        Some(Command::Print {
+            stop,
+            direction,
+            week_of,
+            svg,
+            out,
+        }) => {
+            let week_of = week_of.unwrap_or(today);
+            let Some(stop) = morningstar_cli::get_best_matching_stop_name(
+                stop,
+                tt.stops.values().map(|stop| stop.name.as_str()).collect(),
+            ) else {
+                eprintln!("No stop matching {stop}");
+                return;
+            };
+            let grid = tt.get_printable_grid(&stop, route_id, direction.map(Into::into), week_of);
+            if grid.columns.is_empty() {
+                eprintln!("No departures from {stop} in the week of {week_of}");
+            }
+            let printable = if *svg { grid.to_svg() } else { grid.to_html() };
+            write_or_print(out.as_deref(), &printable);
+            return;
+        }
+        Some(Command::Calendar {
+            from,
+            to,
+            start,
+            days,
+            out,
+        }) => {
+            let stop_names: Vec<_> = tt.stops.values().map(|stop| stop.name.as_str()).collect();
+            let Some(from) = morningstar_cli::get_best_matching_stop_name(from, stop_names.clone())
+            else {
+                eprintln!("No stop matching {from}");
+                return;
+            };
+            let to = match to {
+                Some(to) => match morningstar_cli::get_best_matching_stop_name(to, stop_names) {
+                    Some(to) => Some(to),
+                    None => {
+                        eprintln!("No stop matching {to}");
+                        return;
+                    }
+                },
+                None => None,
+            };
+            let start = start.unwrap_or(today);
+            let end = start + chrono::Days::new(days.saturating_sub(1));
+            let calendar = tt.get_icalendar(
+                &from,
+                to.as_ref().map(StopSelector::from),
+                start,
+                end,
+                route_id,
+            );
+            write_or_print(out.as_deref(), &calendar);
+            return;
+        }
+        Some(Command::Services) => {
+            if tt.holidays.is_empty() {
+                eprintln!(
+                    "The timetable has no school holidays, extract it again with --school-holidays"
+                );
+            }
+            for season in tt.get_service_seasons() {
+                println!("{season}");
+            }
+            return;
+        }
+        Some(Command::RunTimes {
+            from,
+            to,
+            start,
+            days,
+        }) => {
+            let stop_names: Vec<_> = tt.stops.values().map(|stop| stop.name.as_str()).collect();
+            let (Some(from), Some(to)) = (
+                morningstar_cli::get_best_matching_stop_name(from, stop_names.clone()),
+                morningstar_cli::get_best_matching_stop_name(to, stop_names),
+            ) else {
+                eprintln!("No stop matching {from} or {to}");
+                return;
+            };
+            let start = start.unwrap_or(today);
+            let end = start + chrono::Days::new(days.saturating_sub(1));
+            display_run_times(&tt, &from, &to, start..=end, route_id);
+            return;
+        }
+        Some(Command::Stats { stop, week_of }) => {
+            let Some(stop) = morningstar_cli::get_best_matching_stop_name(
+                stop,
+                tt.stops.values().map(|stop| stop.name.as_str()).collect(),
+            ) else {
+                eprintln!("No stop matching {stop}");
+                return;
+            };
+            let week_of = week_of.unwrap_or(today);
+            let stats = tt.get_stop_stats(&stop, route_id, week_of);
+            if stats.is_empty() {
+                eprintln!("No departures from {stop} in the week of {week_of}");
+                return;
+            }
+            println!("{stop}, week of {week_of}");
+            display_stop_stats(&stats);
+            return;
+        }
+    };
+    // Recently departed buses are shown too, they may be running late.
+    let after = now - chrono::Duration::minutes(10);
+    let via = match &board.via {
+        Some(via) => match morningstar_cli::get_best_matching_stop_name(
+            via,
+            tt.stops.values().map(|stop| stop.name.as_str()).collect(),
+        ) {
+            Some(via) => Some(via),
+            None => {
+                eprintln!("No stop matching {via}");
+                return;
+            }
+        },
+        None => None,
+    };
+    let filter = DepartureFilter {
+        direction: board.direction.map(Into::into),
+        terminus: board.towards.as_deref(),
+        calls_at: via.as_ref().map(StopSelector::from),
+    };
+    let quay_id = board.quay.as_deref().map(StopId::from);
+    let (depart_from, quays) = match &quay_id {
+        Some(quay_id) => {
+            let Some(quay) = tt.get_stop(quay_id) else {
+                eprintln!("No stop with id {quay_id}");
+                return;
+            };
+            let stops = if quay.location_type == LocationType::StopArea {
+                StopSelector::Area(quay_id)
+            } else {
+                StopSelector::Id(quay_id)
+            };
+            (quay.name.clone(), vec![(None, stops)])
+        }
+        None => {
+            let Some(depart_from) = ask_or_match_departure_stop(board, &tt, today, route_id) else {
+                eprintln!("failed to ask or match stop name");
+                return;
+            };
+            // Quays sharing the name, usually on both sides of the road, are shown apart when
+            // more than one has departures.
+            let quays: Vec<_> = tt
+                .get_quays(&depart_from)
+                .into_iter()
+                .filter(|quay| {
+                    tt.get_next_departures_from_stop(&after, &quay.id, route_id, filter)
+                        .next()
+                        .is_some()
+                })
+                .map(|quay| (Some(quay), StopSelector::Id(&quay.id)))
+                .collect();
+            if quays.len() > 1 {
+                (depart_from, quays)
+            } else {
+                (depart_from, vec![])
+            }
+        }
     };
     println!("selected start stop {depart_from}");
-    display_next_departures(
-        tt.get_day_stoptimes_from_stop(&today, &depart_from),
-        now,
-        opt,
+    if let Some(via) = &via {
+        println!("only buses calling at {via} later on");
+    }
+    if let Some(holiday) = tt.get_holiday(today) {
+        println!("holiday service today: {holiday}");
+    }
+    let quays = if quays.is_empty() {
+        vec![(None, StopSelector::Name(&depart_from))]
+    } else {
+        quays
+    };
+    // Line names are only worth showing when departures can belong to several lines.
+    let show_route = route_id.is_none() && tt.routes.len() > 1;
+    for (quay, stops) in quays {
+        if let Some(quay) = quay {
+            print!("{}: ", quay_label(&tt, quay, &after, route_id, filter));
+        }
+        let departures = tt
+            .get_next_departures_from_stop(&after, stops, route_id, filter)
+            .map(|dep| Departure {
+                departure: dep.departure,
+                exact_time: dep.exact_time,
+                route_name: dep.route_name.filter(|_| show_route),
+                variant: Some(dep.variant).filter(|_| !dep.main_variant),
+                today,
+            });
+        display_next_departures(departures, now, board.number_to_show.unwrap_or(3));
+    }
+}
+
+/// Stop name picked from the command line or asked for, among stops served today when there
+/// are some.
+fn ask_or_match_departure_stop(
+    board: &BoardArgs,
+    tt: &TimeTable,
+    today: NaiveDate,
+    route_id: Option<&str>,
+) -> Option<String> {
+    let mut stops: Vec<_> = tt
+        .get_stops_served_on_day(&today, route_id)
+        .iter()
+        .copied()
+        .collect();
+    if stops.is_empty() {
+        eprintln!("No stops served today");
+        stops = tt
+            .stops
+            .values()
+            .map(|stop| stop.name.as_str())
+            .collect::<std::collections::HashSet<_>>()
+            .into_iter()
+            .collect();
+    }
+    get_departure_stop(board, stops)
+}
+
+/// Tells a quay from the others sharing its name: by its platform code when it has one, by
+/// where its next buses go otherwise.
+fn quay_label(
+    tt: &TimeTable,
+    quay: &Stop,
+    after: &DateTime<FixedOffset>,
+    route_id: Option<&str>,
+    filter: DepartureFilter<'_>,
+) -> String {
+    if let Some(platform_code) = &quay.platform_code {
+        return format!("platform {platform_code}");
+    }
+    let mut destinations: Vec<String> = vec![];
+    for departure in tt
+        .get_next_departures_from_stop(after, &quay.id, route_id, filter)
+        .take(QUAY_LABEL_DEPARTURES)
+    {
+        if !destinations.contains(&departure.destination) {
+            destinations.push(departure.destination);
+        }
+    }
+    format!("towards {}", destinations.join(", "))
+}
+
+/// Prints scheduled travel times from a stop to another, one line per hour of departure under
+/// a heading for each type of day.
+fn display_run_times(
+    tt: &TimeTable,
+    from: &str,
+    to: &str,
+    dates: std::ops::RangeInclusive<NaiveDate>,
+    route_id: Option<&str>,
+) {
+    println!("{from} to {to}, from {} to {}", dates.start(), dates.end());
+    let run_times = tt.get_run_times(from, to, route_id, dates);
+    if run_times.is_empty() {
+        println!("no journeys");
+        return;
+    }
+    let mut day_type = None;
+    for run_times in run_times {
+        if day_type != Some(run_times.day_type) {
+            day_type = Some(run_times.day_type);
+            println!("{}", run_times.day_type);
+        }
+        println!(
+            "  {:02}h  median {:>3} min  min {:>3}  max {:>3}  ({} journey{})",
+            run_times.hour,
+            run_times.median.num_minutes(),
+            run_times.min.num_minutes(),
+            run_times.max.num_minutes(),
+            run_times.journeys,
+            if run_times.journeys > 1 { "s" } else { "" }
+        );
+    }
+}
+
+/// Prints a line of figures per type of day, then departures per hour with a column per type
+/// of day.
+fn display_stop_stats(stats: &[morningstar_model::stats::StopStats]) {
+    println!(
+        "{:<28} {:<10} {:>5} {:>5} {:>10}  longest gap",
+        "", "date", "first", "last", "departures"
     );
+    for day in stats {
+        let gap = match &day.longest_gap {
+            Some(gap) => format!(
+                "{} to {} ({} min)",
+                gap.from.format("%H:%M"),
+                gap.to.format("%H:%M"),
+                gap.minutes
+            ),
+            None => "-".to_owned(),
+        };
+        println!(
+            "{:<28} {:<10} {:>5} {:>5} {:>10}  {gap}",
+            day.day_type.to_string(),
+            day.date,
+            day.first_departure.format("%H:%M").to_string(),
+            day.last_departure.format("%H:%M").to_string(),
+            day.departures
+        );
+    }
+    println!();
+    print!("hour");
+    for day in stats {
+        print!(" {:>28}", day.day_type.to_string());
+    }
+    println!();
+    let hours: std::collections::BTreeSet<_> = stats
+        .iter()
+        .flat_map(|day| day.departures_per_hour.keys())
+        .collect();
+    for hour in hours {
+        print!("{:02}h ", hour % 24);
+        for day in stats {
+            match day.departures_per_hour.get(hour) {
+                Some(count) => print!(" {count:>28}"),
+                None => print!(" {:>28}", "-"),
+            }
+        }
+        println!();
+    }
 }
 
-fn display_next_departures<'a, I>(iter: I, now: NaiveTime, opt: Opt)
+/// Writes to the file when provided, to standard output otherwise.
+fn write_or_print(out: Option<&std::path::Path>, content: &str) {
+    match out {
+        Some(out) => {
+            if let Err(err) = std::fs::write(out, content) {
+                eprintln!("{}: {err}", out.display());
+            }
+        }
+        None => print!("{content}"),
+    }
+}
+
+/// A departure as displayed: its time, marked with `~` when the operator only commits to a
+/// headway, its day when it isn't today, its line name when worth showing, and its stop pattern
+/// when it isn't the main one.
+struct Departure {
+    departure: DateTime<FixedOffset>,
+    exact_time: bool,
+    route_name: Option<String>,
+    variant: Option<String>,
+    today: NaiveDate,
+}
+
+impl std::fmt::Display for Departure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.departure.date_naive() != self.today {
+            write!(f, "{} ", self.departure.format("%a"))?;
+        }
+        if !self.exact_time {
+            write!(f, "~")?;
+        }
+        write!(
+            f,
+            "{:02}:{:02}{}",
+            self.departure.hour(),
+            self.departure.minute(),
+            RouteLabel(self.route_name.as_deref())
+        )?;
+        match &self.variant {
+            Some(variant) => write!(f, " ({variant})"),
+            None => Ok(()),
+        }
+    }
+}
+
+fn display_next_departures<I>(iter: I, now: DateTime<FixedOffset>, number_to_show: usize)
 where
-    I: Iterator<Item = &'a morningstar_model::StopTime>,
+    I: Iterator<Item = Departure>,
 {
-    iter.map(|dep| (dep.time.signed_duration_since(now).num_minutes(), dep))
-        .filter(|(minutes_from_now, a)| {
-            if *minutes_from_now < -10 {
-                false
-            } else if *minutes_from_now >= -10 && *minutes_from_now < 0 {
-                print!(
-                    "{:02}:{:02} (due {} minutes ago), ",
-                    a.time.hour(),
-                    a.time.minute(),
-                    minutes_from_now * -1
-                );
-                false
+    iter.map(|departure| {
+        (
+            departure.departure.signed_duration_since(now).num_minutes(),
+            departure,
+        )
+    })
+    .filter(|(minutes_from_now, departure)| {
+        if *minutes_from_now < -10 {
+            false
+        } else if *minutes_from_now >= -10 && *minutes_from_now < 0 {
+            print!("{departure} (due {} minutes ago), ", -minutes_from_now);
+            false
+        } else {
+            true
+        }
+    })
+    .take(number_to_show)
+    .for_each(|(_, departure)| {
+        print!("{departure}, ");
+    });
+    println!("...");
+}
+
+/// Displays as the bracketed line name when there is one, nothing otherwise.
+struct RouteLabel<'a>(Option<&'a str>);
+
+impl std::fmt::Display for RouteLabel<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(route_name) => write!(f, " [{route_name}]"),
+            None => Ok(()),
+        }
+    }
+}
+
+fn display_round_trips(
+    tt: &morningstar_model::TimeTable,
+    from: &str,
+    to: &str,
+    day: NaiveDate,
+    stay: Stay,
+    route_id: Option<&str>,
+) {
+    let stops: Vec<_> = tt
+        .get_stops_served_on_day(&day, route_id)
+        .iter()
+        .copied()
+        .collect();
+    let (Some(from), Some(to)) = (
+        morningstar_cli::get_best_matching_stop_name(from, stops.clone()),
+        morningstar_cli::get_best_matching_stop_name(to, stops),
+    ) else {
+        eprintln!("No stops matching {from} and {to} served on {day}");
+        return;
+    };
+    println!("round trips from {from} to {to} on {day}");
+    let round_trips = tt.get_day_round_trips(&day, &from, &to, stay, route_id);
+    if round_trips.is_empty() {
+        println!("No way to go there and back");
+    }
+    let tz = tt.tz();
+    for round_trip in round_trips {
+        let (board, alight) = round_trip.outbound;
+        let (return_board, return_alight) = round_trip.inbound;
+        println!(
+            "{} -> {}, {}h{:02} there, {} -> {}{}",
+            board.departure_time_of_day(&tz).format("%H:%M"),
+            alight.arrival_time_of_day(&tz).format("%H:%M"),
+            round_trip.stay.num_hours(),
+            round_trip.stay.num_minutes() % 60,
+            return_board.departure_time_of_day(&tz).format("%H:%M"),
+            return_alight.arrival_time_of_day(&tz).format("%H:%M"),
+            if round_trip.last_return {
+                " (last bus back)"
             } else {
-                true
+                ""
             }
-        })
-        .take(opt.number_to_show.unwrap_or(3))
-        .for_each(|(_, a)| {
-            print!("{:02}:{:02}, ", a.time.hour(), a.time.minute(),);
-        });
-    println!("...");
+        );
+    }
 }
 
-fn get_departure_stop(opt: &Opt, stops: Vec<&str>) -> Option<String> {
-    if let Some(depart_from) = &opt.depart_from {
+fn get_departure_stop(board: &BoardArgs, stops: Vec<&str>) -> Option<String> {
+    if let Some(depart_from) = &board.depart_from {
         morningstar_cli::get_best_matching_stop_name(depart_from, stops)
     } else {
         ask_for_deperture_stop(stops)
@@ -92,7 +663,16 @@ fn ask_for_deperture_stop(mut stops: Vec<&str>) -> Option<String> {
     use inquire::{error::InquireError, Select};
 
     stops.sort();
-    let ans: Result<&str, InquireError> = Select::new("Depart from?", stops).prompt();
+    // Same matching as stop names given on the command line, best matches first.
+    let scorer = |input: &str, _: &&str, name: &str, _: usize| {
+        if input.is_empty() {
+            return Some(0);
+        }
+        morningstar_model::search::score(input, name).map(|score| (score * 1000.0) as i64)
+    };
+    let ans: Result<&str, InquireError> = Select::new("Depart from?", stops)
+        .with_scorer(&scorer)
+        .prompt();
     match ans {
         Ok(ans) => Some(ans.to_owned()),
         Err(err) => {
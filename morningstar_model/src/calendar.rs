@@ -0,0 +1,147 @@
+//! Dates on which services run, the other way round from asking whether a service runs on a
+//! given date.
+
+use crate::TimeTable;
+use chrono::NaiveDate;
+use std::collections::{BTreeSet, HashSet};
+use std::ops::RangeInclusive;
+
+impl TimeTable {
+    /// Dates on which the service runs, following its pattern and exceptions.
+    pub fn get_service_dates(&self, service_id: &str) -> BTreeSet<NaiveDate> {
+        let pattern_dates = self
+            .service_patterns
+            .get(service_id)
+            .into_iter()
+            .flat_map(|pattern| {
+                pattern
+                    .start_date
+                    .iter_days()
+                    .take_while(|day| *day <= pattern.end_date)
+            });
+        let exception_dates = self
+            .exceptions
+            .get_vec(service_id)
+            .into_iter()
+            .flatten()
+            .map(|exception| exception.date);
+        pattern_dates
+            .chain(exception_dates)
+            .filter(|day| self.service_runs_on(service_id, *day))
+            .collect()
+    }
+
+    /// First and last service dates of the timetable's journeys, `None` when none ever runs.
+    pub fn get_validity_window(&self) -> Option<RangeInclusive<NaiveDate>> {
+        let dates = self.get_journeys_service_dates();
+        Some(*dates.first()?..=*dates.last()?)
+    }
+
+    /// Dates from `start` to `end` included on which none of the timetable's journeys run.
+    pub fn get_dates_without_service(&self, start: NaiveDate, end: NaiveDate) -> Vec<NaiveDate> {
+        let dates = self.get_journeys_service_dates();
+        start
+            .iter_days()
+            .take_while(|day| *day <= end)
+            .filter(|day| !dates.contains(day))
+            .collect()
+    }
+
+    /// Last date of service when it comes less than `days` days after `today`, including when
+    /// it has already passed, so that users can be told to update the timetable.
+    pub fn expires_within(&self, today: NaiveDate, days: u64) -> Option<NaiveDate> {
+        let last_date = *self.get_validity_window()?.end();
+        ((last_date - today).num_days() < days as i64).then_some(last_date)
+    }
+
+    fn get_journeys_service_dates(&self) -> BTreeSet<NaiveDate> {
+        let service_ids: HashSet<_> = self
+            .journeys
+            .iter()
+            .map(|journey| journey.service_id.as_str())
+            .collect();
+        service_ids
+            .into_iter()
+            .flat_map(|service_id| self.get_service_dates(service_id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::*;
+    use crate::*;
+
+    /// Workdays until mid-March, except new year's day, and an extra Saturday at the end.
+    fn sample_tt() -> TimeTable {
+        let mut tt = TimeTable::new();
+        tt.service_patterns.insert(
+            "wd1".to_owned(),
+            ServicePattern {
+                weekdays: WeekdayFlags::WORKDAYS,
+                start_date: date(2024, 1, 1),
+                end_date: date(2024, 3, 15),
+            },
+        );
+        tt.exceptions.insert(
+            "wd1".to_owned(),
+            ServiceException {
+                date: date(2024, 1, 1),
+                exception_type: Exception::Deleted,
+            },
+        );
+        tt.exceptions.insert(
+            "wd1".to_owned(),
+            ServiceException {
+                date: date(2024, 3, 16),
+                exception_type: Exception::Added,
+            },
+        );
+        // Never used by a journey.
+        tt.service_patterns.insert(
+            "we1".to_owned(),
+            ServicePattern {
+                weekdays: WeekdayFlags::WEEKENDS,
+                start_date: date(2024, 1, 1),
+                end_date: date(2024, 12, 31),
+            },
+        );
+        tt.journeys.push(trip("t1", "wd1", &[]));
+        tt
+    }
+
+    #[test]
+    fn service_dates() {
+        let tt = sample_tt();
+        let dates = tt.get_service_dates("wd1");
+        assert_eq!(dates.first(), Some(&date(2024, 1, 2)));
+        assert_eq!(dates.last(), Some(&date(2024, 3, 16)));
+        assert!(!dates.contains(&date(2024, 1, 6)));
+        // 54 workdays from January 2nd to March 15th, and the added Saturday.
+        assert_eq!(dates.len(), 55);
+        assert!(tt.get_service_dates("unknown").is_empty());
+    }
+
+    #[test]
+    fn validity() {
+        let tt = sample_tt();
+        assert_eq!(
+            tt.get_validity_window(),
+            Some(date(2024, 1, 2)..=date(2024, 3, 16))
+        );
+        assert_eq!(
+            tt.get_dates_without_service(date(2024, 3, 14), date(2024, 3, 19)),
+            [date(2024, 3, 17), date(2024, 3, 18), date(2024, 3, 19)]
+        );
+        assert_eq!(
+            tt.expires_within(date(2024, 3, 10), 14),
+            Some(date(2024, 3, 16))
+        );
+        assert_eq!(
+            tt.expires_within(date(2024, 4, 1), 14),
+            Some(date(2024, 3, 16))
+        );
+        assert_eq!(tt.expires_within(date(2024, 1, 10), 14), None);
+        assert_eq!(TimeTable::new().get_validity_window(), None);
+    }
+}
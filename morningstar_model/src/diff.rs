@@ -0,0 +1,564 @@
+//! What changed between two extractions of a timetable, typically from one published feed to the
+//! next.
+//!
+//! Journeys are matched by trip id first. Feeds tend to renumber trips, so the remaining ones are
+//! then matched on their route and sequence of stops, pairing journeys whose first departures
+//! are the closest and no more than `PATTERN_MATCH_WINDOW_SECS` apart.
+
+use crate::{Journey, ServiceTime, StopId, TimeTable};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Display;
+
+/// Journeys on the same stop pattern whose first departures are further apart than this are
+/// considered to be different journeys rather than a retimed one.
+const PATTERN_MATCH_WINDOW_SECS: u32 = 30 * 60;
+
+#[derive(Debug, Default, Serialize)]
+pub struct TimeTableDiff {
+    pub added_journeys: Vec<JourneySummary>,
+    pub removed_journeys: Vec<JourneySummary>,
+    pub changed_journeys: Vec<ChangedJourney>,
+    pub added_stops: Vec<StopSummary>,
+    pub removed_stops: Vec<StopSummary>,
+    pub renamed_stops: Vec<RenamedStop>,
+    pub calendar_changes: Vec<CalendarChange>,
+    /// Changes to departures, by stop name.
+    pub stops: Vec<StopChanges>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JourneySummary {
+    pub trip_id: String,
+    pub route_id: String,
+    pub service_id: String,
+    pub departure: ServiceTime,
+    pub origin: String,
+    pub destination: String,
+}
+
+/// Journey found in both timetables whose calls were retimed, added or removed.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangedJourney {
+    pub old: JourneySummary,
+    pub new: JourneySummary,
+    pub moved_calls: Vec<MovedCall>,
+    pub added_calls: Vec<Call>,
+    pub removed_calls: Vec<Call>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Call {
+    pub stop_id: StopId,
+    pub stop_name: String,
+    pub departure: ServiceTime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MovedCall {
+    pub stop_id: StopId,
+    pub stop_name: String,
+    pub old_departure: ServiceTime,
+    pub new_departure: ServiceTime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StopSummary {
+    pub stop_id: StopId,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RenamedStop {
+    pub stop_id: StopId,
+    pub old_name: String,
+    pub new_name: String,
+}
+
+/// Dates a service id gained or lost. Services only found in one timetable gain or lose all of
+/// their dates.
+#[derive(Debug, Clone, Serialize)]
+pub struct CalendarChange {
+    pub service_id: String,
+    pub added_dates: Vec<chrono::NaiveDate>,
+    pub removed_dates: Vec<chrono::NaiveDate>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StopChanges {
+    pub stop_name: String,
+    pub added_departures: usize,
+    pub removed_departures: usize,
+    pub moved_departures: usize,
+}
+
+impl TimeTableDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_journeys.is_empty()
+            && self.removed_journeys.is_empty()
+            && self.changed_journeys.is_empty()
+            && self.added_stops.is_empty()
+            && self.removed_stops.is_empty()
+            && self.renamed_stops.is_empty()
+            && self.calendar_changes.is_empty()
+    }
+}
+
+impl TimeTable {
+    /// Changes from this timetable to a newer one.
+    pub fn diff(&self, newer: &TimeTable) -> TimeTableDiff {
+        let mut diff = TimeTableDiff::default();
+        let mut stops: BTreeMap<String, StopChanges> = BTreeMap::new();
+
+        let (matches, removed, added) = match_journeys(&self.journeys, &newer.journeys);
+        for (old_idx, new_idx) in matches {
+            let (old, new) = (&self.journeys[old_idx], &newer.journeys[new_idx]);
+            let moved_calls = moved_calls(old, new, newer);
+            let added_calls = calls_missing_from(new, old, newer);
+            let removed_calls = calls_missing_from(old, new, self);
+            if moved_calls.is_empty() && added_calls.is_empty() && removed_calls.is_empty() {
+                continue;
+            }
+            for call in &moved_calls {
+                stop_changes(&mut stops, &call.stop_name).moved_departures += 1;
+            }
+            for call in &added_calls {
+                stop_changes(&mut stops, &call.stop_name).added_departures += 1;
+            }
+            for call in &removed_calls {
+                stop_changes(&mut stops, &call.stop_name).removed_departures += 1;
+            }
+            diff.changed_journeys.push(ChangedJourney {
+                old: journey_summary(old, self),
+                new: journey_summary(new, newer),
+                moved_calls,
+                added_calls,
+                removed_calls,
+            });
+        }
+        for old_idx in removed {
+            let journey = &self.journeys[old_idx];
+            for stop in &journey.stops {
+                stop_changes(&mut stops, self.get_stop_name(&stop.stop_id)).removed_departures += 1;
+            }
+            diff.removed_journeys.push(journey_summary(journey, self));
+        }
+        for new_idx in added {
+            let journey = &newer.journeys[new_idx];
+            for stop in &journey.stops {
+                stop_changes(&mut stops, newer.get_stop_name(&stop.stop_id)).added_departures += 1;
+            }
+            diff.added_journeys.push(journey_summary(journey, newer));
+        }
+        diff.stops = stops.into_values().collect();
+
+        for stop in newer.stops.values() {
+            match self.stops.get(&stop.id) {
+                None => diff.added_stops.push(StopSummary {
+                    stop_id: stop.id.clone(),
+                    name: stop.name.clone(),
+                }),
+                Some(old) if old.name != stop.name => diff.renamed_stops.push(RenamedStop {
+                    stop_id: stop.id.clone(),
+                    old_name: old.name.clone(),
+                    new_name: stop.name.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+        diff.removed_stops = self
+            .stops
+            .values()
+            .filter(|stop| !newer.stops.contains_key(&stop.id))
+            .map(|stop| StopSummary {
+                stop_id: stop.id.clone(),
+                name: stop.name.clone(),
+            })
+            .collect();
+        diff.added_stops.sort_by(|a, b| a.stop_id.cmp(&b.stop_id));
+        diff.removed_stops.sort_by(|a, b| a.stop_id.cmp(&b.stop_id));
+        diff.renamed_stops.sort_by(|a, b| a.stop_id.cmp(&b.stop_id));
+
+        diff.calendar_changes = calendar_changes(self, newer);
+        diff
+    }
+}
+
+fn stop_changes<'a>(
+    stops: &'a mut BTreeMap<String, StopChanges>,
+    stop_name: &str,
+) -> &'a mut StopChanges {
+    stops
+        .entry(stop_name.to_owned())
+        .or_insert_with(|| StopChanges {
+            stop_name: stop_name.to_owned(),
+            ..Default::default()
+        })
+}
+
+/// Pairs of matched (old, new) journey indices, then unmatched old ones and unmatched new ones.
+fn match_journeys(
+    old: &[Journey],
+    new: &[Journey],
+) -> (Vec<(usize, usize)>, Vec<usize>, Vec<usize>) {
+    let old_by_trip_id: HashMap<_, _> = old
+        .iter()
+        .enumerate()
+        .map(|(idx, journey)| (journey.trip_id.as_str(), idx))
+        .collect();
+    let mut matches = vec![];
+    let mut matched_old = HashSet::new();
+    let mut unmatched_new = vec![];
+    for (new_idx, journey) in new.iter().enumerate() {
+        match old_by_trip_id.get(journey.trip_id.as_str()) {
+            Some(old_idx) => {
+                matches.push((*old_idx, new_idx));
+                matched_old.insert(*old_idx);
+            }
+            None => unmatched_new.push(new_idx),
+        }
+    }
+
+    let pattern = |journey: &Journey| {
+        let stop_ids: Vec<_> = journey
+            .stops
+            .iter()
+            .map(|stop| stop.stop_id.clone())
+            .collect();
+        (journey.route_id.clone(), stop_ids)
+    };
+    let mut old_by_pattern: HashMap<_, Vec<usize>> = HashMap::new();
+    for (old_idx, journey) in old.iter().enumerate() {
+        if !matched_old.contains(&old_idx) {
+            old_by_pattern
+                .entry(pattern(journey))
+                .or_default()
+                .push(old_idx);
+        }
+    }
+    let mut added = vec![];
+    for new_idx in unmatched_new {
+        let departure = first_departure(&new[new_idx]);
+        let candidates = old_by_pattern.entry(pattern(&new[new_idx])).or_default();
+        let closest = candidates
+            .iter()
+            .enumerate()
+            .map(|(position, old_idx)| {
+                (
+                    position,
+                    first_departure(&old[*old_idx]).abs_diff(departure),
+                )
+            })
+            .filter(|(_, distance)| *distance <= PATTERN_MATCH_WINDOW_SECS)
+            .min_by_key(|(_, distance)| *distance);
+        match closest {
+            Some((position, _)) => {
+                let old_idx = candidates.swap_remove(position);
+                matches.push((old_idx, new_idx));
+                matched_old.insert(old_idx);
+            }
+            None => added.push(new_idx),
+        }
+    }
+    let removed = (0..old.len())
+        .filter(|old_idx| !matched_old.contains(old_idx))
+        .collect();
+    (matches, removed, added)
+}
+
+fn first_departure(journey: &Journey) -> u32 {
+    journey
+        .stops
+        .first()
+        .map(|stop| stop.departure_time.seconds())
+        .unwrap_or_default()
+}
+
+/// Calls at stops both journeys serve whose departure changed, stops being looked up in the newer
+/// timetable.
+fn moved_calls(old: &Journey, new: &Journey, newer: &TimeTable) -> Vec<MovedCall> {
+    new.stops
+        .iter()
+        .filter_map(|new_stop| {
+            let old_stop = old
+                .stops
+                .iter()
+                .find(|old_stop| old_stop.stop_id == new_stop.stop_id)?;
+            (old_stop.departure_time != new_stop.departure_time).then(|| MovedCall {
+                stop_id: new_stop.stop_id.clone(),
+                stop_name: newer.get_stop_name(&new_stop.stop_id).to_owned(),
+                old_departure: old_stop.departure_time,
+                new_departure: new_stop.departure_time,
+            })
+        })
+        .collect()
+}
+
+/// Calls of `journey` at stops `other` doesn't serve, stops being looked up in `tt`.
+fn calls_missing_from(journey: &Journey, other: &Journey, tt: &TimeTable) -> Vec<Call> {
+    journey
+        .stops
+        .iter()
+        .filter(|stop| {
+            !other
+                .stops
+                .iter()
+                .any(|other_stop| other_stop.stop_id == stop.stop_id)
+        })
+        .map(|stop| Call {
+            stop_id: stop.stop_id.clone(),
+            stop_name: tt.get_stop_name(&stop.stop_id).to_owned(),
+            departure: stop.departure_time,
+        })
+        .collect()
+}
+
+fn journey_summary(journey: &Journey, tt: &TimeTable) -> JourneySummary {
+    let stop_name = |stop: Option<&crate::StopTime>| {
+        stop.map(|stop| tt.get_stop_name(&stop.stop_id).to_owned())
+            .unwrap_or_default()
+    };
+    JourneySummary {
+        trip_id: journey.trip_id.clone(),
+        route_id: journey.route_id.clone(),
+        service_id: journey.service_id.clone(),
+        departure: ServiceTime::from_seconds(first_departure(journey)),
+        origin: stop_name(journey.stops.first()),
+        destination: stop_name(journey.stops.last()),
+    }
+}
+
+fn calendar_changes(old: &TimeTable, new: &TimeTable) -> Vec<CalendarChange> {
+    let service_ids: std::collections::BTreeSet<_> = old
+        .journeys
+        .iter()
+        .chain(&new.journeys)
+        .map(|journey| journey.service_id.as_str())
+        .collect();
+    service_ids
+        .into_iter()
+        .filter_map(|service_id| {
+            let (old_dates, new_dates) = (
+                old.get_service_dates(service_id),
+                new.get_service_dates(service_id),
+            );
+            let change = CalendarChange {
+                service_id: service_id.to_owned(),
+                added_dates: new_dates.difference(&old_dates).copied().collect(),
+                removed_dates: old_dates.difference(&new_dates).copied().collect(),
+            };
+            (!change.added_dates.is_empty() || !change.removed_dates.is_empty()).then_some(change)
+        })
+        .collect()
+}
+
+impl Display for JourneySummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} -> {} (trip {}, route {}, service {})",
+            self.departure,
+            self.origin,
+            self.destination,
+            self.trip_id,
+            self.route_id,
+            self.service_id
+        )
+    }
+}
+
+impl Display for TimeTableDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        if !self.added_journeys.is_empty() {
+            writeln!(f, "== {} added journeys ==", self.added_journeys.len())?;
+            for journey in &self.added_journeys {
+                writeln!(f, "+ {journey}")?;
+            }
+        }
+        if !self.removed_journeys.is_empty() {
+            writeln!(f, "== {} removed journeys ==", self.removed_journeys.len())?;
+            for journey in &self.removed_journeys {
+                writeln!(f, "- {journey}")?;
+            }
+        }
+        if !self.changed_journeys.is_empty() {
+            writeln!(f, "== {} changed journeys ==", self.changed_journeys.len())?;
+            for journey in &self.changed_journeys {
+                writeln!(f, "~ {}", journey.new)?;
+                for call in &journey.moved_calls {
+                    writeln!(
+                        f,
+                        "    {}: {} -> {}",
+                        call.stop_name, call.old_departure, call.new_departure
+                    )?;
+                }
+                for call in &journey.added_calls {
+                    writeln!(f, "    + {}: {}", call.stop_name, call.departure)?;
+                }
+                for call in &journey.removed_calls {
+                    writeln!(f, "    - {}: {}", call.stop_name, call.departure)?;
+                }
+            }
+        }
+        if !self.added_stops.is_empty() || !self.removed_stops.is_empty() {
+            writeln!(f, "== stops ==")?;
+            for stop in &self.added_stops {
+                writeln!(f, "+ {} ({})", stop.name, stop.stop_id)?;
+            }
+            for stop in &self.removed_stops {
+                writeln!(f, "- {} ({})", stop.name, stop.stop_id)?;
+            }
+        }
+        if !self.renamed_stops.is_empty() {
+            writeln!(f, "== renamed stops ==")?;
+            for stop in &self.renamed_stops {
+                writeln!(
+                    f,
+                    "{} -> {} ({})",
+                    stop.old_name, stop.new_name, stop.stop_id
+                )?;
+            }
+        }
+        if !self.calendar_changes.is_empty() {
+            writeln!(f, "== calendars ==")?;
+            for change in &self.calendar_changes {
+                writeln!(
+                    f,
+                    "{}: {} dates added{}, {} dates removed{}",
+                    change.service_id,
+                    change.added_dates.len(),
+                    DateSpan(&change.added_dates),
+                    change.removed_dates.len(),
+                    DateSpan(&change.removed_dates)
+                )?;
+            }
+        }
+        if !self.stops.is_empty() {
+            writeln!(f, "== departures by stop ==")?;
+            for stop in &self.stops {
+                writeln!(
+                    f,
+                    "{}: +{} -{} ~{}",
+                    stop.stop_name,
+                    stop.added_departures,
+                    stop.removed_departures,
+                    stop.moved_departures
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Displays as the first and last of sorted dates, nothing when there are none.
+struct DateSpan<'a>(&'a [chrono::NaiveDate]);
+
+impl Display for DateSpan<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            [] => Ok(()),
+            [date] => write!(f, " ({date})"),
+            [first, .., last] => write!(f, " ({first} to {last})"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::*;
+    use crate::*;
+
+    fn sample_tt(stops: &[(&str, &str)], journeys: Vec<Journey>, end_month: u32) -> TimeTable {
+        let mut tt = TimeTable::new();
+        add_stops(&mut tt, stops);
+        add_service(&mut tt, "wd1", WeekdayFlags::WORKDAYS);
+        tt.service_patterns.get_mut("wd1").unwrap().end_date = date(2024, end_month, 1);
+        tt.journeys = journeys;
+        tt
+    }
+
+    #[test]
+    fn changes() {
+        let old = sample_tt(
+            &[("a", "Église"), ("b", "Marché"), ("c", "Gare")],
+            vec![
+                trip("t1", "wd1", &[(8, 0, "a"), (8, 10, "b"), (8, 20, "c")]),
+                trip("t2", "wd1", &[(9, 0, "a"), (9, 10, "b"), (9, 20, "c")]),
+                trip("t3", "wd1", &[(18, 0, "a"), (18, 10, "b"), (18, 20, "c")]),
+            ],
+            6,
+        );
+        let new = sample_tt(
+            &[("a", "Église"), ("b", "Place du Marché"), ("d", "Mairie")],
+            vec![
+                // Same trip id, a minute later at the market and cut short there.
+                trip("t1", "wd1", &[(8, 0, "a"), (8, 11, "b")]),
+                // Renumbered and five minutes earlier.
+                trip("n2", "wd1", &[(8, 55, "a"), (9, 5, "b"), (9, 15, "c")]),
+                trip("n4", "wd1", &[(12, 0, "a"), (12, 15, "d")]),
+            ],
+            7,
+        );
+        let diff = old.diff(&new);
+        let trip_ids = |journeys: &[diff::JourneySummary]| -> Vec<String> {
+            journeys
+                .iter()
+                .map(|journey| journey.trip_id.clone())
+                .collect()
+        };
+        assert_eq!(trip_ids(&diff.added_journeys), ["n4"]);
+        assert_eq!(trip_ids(&diff.removed_journeys), ["t3"]);
+        assert_eq!(diff.changed_journeys.len(), 2);
+        let cut_short = &diff.changed_journeys[0];
+        assert_eq!(cut_short.moved_calls.len(), 1);
+        assert!(cut_short.added_calls.is_empty());
+        assert_eq!(cut_short.removed_calls.len(), 1);
+        assert_eq!(cut_short.removed_calls[0].stop_name, "Gare");
+        assert_eq!(
+            cut_short.removed_calls[0].departure,
+            ServiceTime::from_hms(8, 20, 0)
+        );
+        assert_eq!(diff.changed_journeys[1].old.trip_id, "t2");
+        assert_eq!(diff.changed_journeys[1].moved_calls.len(), 3);
+        assert_eq!(diff.added_stops[0].name, "Mairie");
+        assert_eq!(diff.removed_stops[0].name, "Gare");
+        assert_eq!(diff.renamed_stops[0].new_name, "Place du Marché");
+        assert_eq!(diff.calendar_changes.len(), 1);
+        assert_eq!(diff.calendar_changes[0].added_dates.len(), 21);
+        assert!(diff.calendar_changes[0].removed_dates.is_empty());
+        let eglise = diff
+            .stops
+            .iter()
+            .find(|stop| stop.stop_name == "Église")
+            .unwrap();
+        assert_eq!(
+            (
+                eglise.added_departures,
+                eglise.removed_departures,
+                eglise.moved_departures
+            ),
+            (1, 1, 1)
+        );
+        // Removed with t3, and no longer served by t1.
+        let gare = diff
+            .stops
+            .iter()
+            .find(|stop| stop.stop_name == "Gare")
+            .unwrap();
+        assert_eq!(
+            (
+                gare.added_departures,
+                gare.removed_departures,
+                gare.moved_departures
+            ),
+            (0, 2, 0)
+        );
+        assert!(diff.to_string().contains("Marché -> Place du Marché"));
+        assert!(diff.to_string().contains("    - Gare: 08:20:00\n"));
+        assert!(serde_json::to_value(&diff).is_ok());
+        assert!(new.diff(&new).is_empty());
+    }
+}
@@ -0,0 +1,93 @@
+//! Departures from a stop narrowed down to those going one way, for stops served in both
+//! directions.
+//!
+//! The way can be given by the route direction, by the terminus, or by a stop the bus must call
+//! at later on. The last one also keeps journeys on variants ending somewhere else, as long as
+//! they go past that stop.
+
+use crate::{Direction, Journey, StopSelector, TimeTable};
+use std::collections::BTreeMap;
+
+/// Which departures from a stop to keep. Conditions add up, the default keeps them all.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DepartureFilter<'a> {
+    /// Only journeys travelling this way along their route.
+    pub direction: Option<Direction>,
+    /// Only journeys ending there: the destination they show or the name of their last stop,
+    /// case aside.
+    pub terminus: Option<&'a str>,
+    /// Only journeys calling later on at one of these stops, where passengers can get off.
+    pub calls_at: Option<StopSelector<'a>>,
+}
+
+impl DepartureFilter<'_> {
+    /// Whether every departure is kept.
+    pub fn is_empty(&self) -> bool {
+        self.direction.is_none() && self.terminus.is_none() && self.calls_at.is_none()
+    }
+}
+
+/// A filter with the stops it refers to looked up, to check departures against.
+pub(crate) struct PreparedFilter<'a> {
+    filter: DepartureFilter<'a>,
+    /// Positions of the calls at the `calls_at` stops, by journey index.
+    later_calls: Option<BTreeMap<usize, Vec<usize>>>,
+}
+
+impl TimeTable {
+    pub(crate) fn prepare_filter<'a>(&self, filter: DepartureFilter<'a>) -> PreparedFilter<'a> {
+        PreparedFilter {
+            filter,
+            later_calls: filter
+                .calls_at
+                .map(|stops| self.index().calls_at(self.get_stop_ids(stops))),
+        }
+    }
+
+    /// Whether the journey ends at the provided terminus.
+    fn journey_ends_at(&self, journey: &Journey, terminus: &str) -> bool {
+        let terminus = terminus.to_lowercase();
+        let last_stop = journey
+            .stops
+            .last()
+            .map(|stop| self.get_stop_name(&stop.stop_id));
+        [Some(self.get_journey_destination(journey)), last_stop]
+            .into_iter()
+            .flatten()
+            .any(|name| name.to_lowercase() == terminus)
+    }
+}
+
+impl PreparedFilter<'_> {
+    /// Whether the departure from the journey's stop at `position` is kept.
+    pub(crate) fn keeps(
+        &self,
+        tt: &TimeTable,
+        journey_idx: usize,
+        journey: &Journey,
+        position: usize,
+    ) -> bool {
+        if self
+            .filter
+            .direction
+            .is_some_and(|direction| journey.direction_id != Some(direction))
+        {
+            return false;
+        }
+        if self
+            .filter
+            .terminus
+            .is_some_and(|terminus| !tt.journey_ends_at(journey, terminus))
+        {
+            return false;
+        }
+        let Some(later_calls) = &self.later_calls else {
+            return true;
+        };
+        later_calls.get(&journey_idx).is_some_and(|positions| {
+            positions
+                .iter()
+                .any(|later| *later > position && journey.stops[*later].can_alight())
+        })
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{Journey, ServiceTime, StopTime};
+use serde::{Deserialize, Serialize};
+
+/// Service repeating a journey every `headway_secs` over a period of the day, mirroring a GTFS
+/// `frequencies.txt` entry.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Frequency {
+    /// Departure of the first journey from its first stop.
+    pub start_time: ServiceTime,
+    /// No journey leaves its first stop at or after this time.
+    pub end_time: ServiceTime,
+    pub headway_secs: u32,
+    /// Whether journeys leave exactly every `headway_secs`. When they don't, the operator only
+    /// commits to the headway and departure times are estimates.
+    pub exact_times: bool,
+}
+
+impl Frequency {
+    /// Times at which journeys leave their first stop.
+    pub fn departures(&self) -> impl Iterator<Item = ServiceTime> {
+        let (start, end) = (self.start_time.seconds(), self.end_time.seconds());
+        // A zero headway would never reach the end time.
+        let step = self.headway_secs.max(1) as usize;
+        (start..end).step_by(step).map(ServiceTime::from_seconds)
+    }
+}
+
+impl Journey {
+    /// Journeys running following the frequency, this journey's stop times being shifted to
+    /// each departure. Generated trip ids are suffixed with the departure time.
+    pub fn expand_frequency(&self, frequency: Frequency) -> impl Iterator<Item = Journey> + '_ {
+        let first_departure = self
+            .stops
+            .first()
+            .map(|stop| stop.departure_time.seconds() as i64)
+            .unwrap_or_default();
+        frequency.departures().map(move |departure| {
+            let shift = departure.seconds() as i64 - first_departure;
+            let shift_time = |time: ServiceTime| {
+                ServiceTime::from_seconds((time.seconds() as i64 + shift).max(0) as u32)
+            };
+            Journey {
+                trip_id: format!("{}@{departure}", self.trip_id),
+                stops: self
+                    .stops
+                    .iter()
+                    .map(|stop| StopTime {
+                        arrival_time: shift_time(stop.arrival_time),
+                        departure_time: shift_time(stop.departure_time),
+                        ..stop.clone()
+                    })
+                    .collect(),
+                frequency: Some(frequency),
+                ..self.clone()
+            }
+        })
+    }
+
+    /// Whether stop times are the ones the journey keeps, rather than estimates from a headway.
+    pub fn has_exact_times(&self) -> bool {
+        self.frequency.is_none_or(|frequency| frequency.exact_times)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+
+    fn template() -> Journey {
+        trip(
+            "t1",
+            "wd1",
+            &[(0, 0, "eglise"), (0, 6, "marche"), (0, 15, "gare")],
+        )
+    }
+
+    #[test]
+    fn expand() {
+        let frequency = Frequency {
+            start_time: ServiceTime::from_hms(7, 0, 0),
+            end_time: ServiceTime::from_hms(8, 0, 0),
+            headway_secs: 12 * 60,
+            exact_times: true,
+        };
+        let journeys: Vec<_> = template().expand_frequency(frequency).collect();
+        let departures: Vec<_> = journeys
+            .iter()
+            .map(|journey| journey.stops[0].departure_time.to_string())
+            .collect();
+        assert_eq!(
+            departures,
+            ["07:00:00", "07:12:00", "07:24:00", "07:36:00", "07:48:00"]
+        );
+        assert_eq!(journeys[1].trip_id, "t1@07:12:00");
+        assert_eq!(
+            journeys[1].stops[2].arrival_time,
+            ServiceTime::from_hms(7, 27, 0)
+        );
+        assert!(journeys.iter().all(Journey::has_exact_times));
+    }
+
+    #[test]
+    fn headway_only() {
+        let frequency = Frequency {
+            start_time: ServiceTime::from_hms(23, 30, 0),
+            end_time: ServiceTime::from_hms(24, 30, 0),
+            headway_secs: 30 * 60,
+            exact_times: false,
+        };
+        let journeys: Vec<_> = template().expand_frequency(frequency).collect();
+        assert_eq!(journeys.len(), 2);
+        assert!(journeys[1].runs_past_midnight());
+        assert!(!journeys[0].has_exact_times());
+        assert!(template().has_exact_times());
+    }
+}
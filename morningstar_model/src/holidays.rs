@@ -0,0 +1,347 @@
+//! French public holidays and school holidays, which is when buses usually run another service.
+//!
+//! Public holidays are computed, those following Easter with the Gregorian computus. School
+//! holidays differ between zones and are published each year, so they are read from a RON file
+//! listing periods such as:
+//!
+//! ```ron
+//! [
+//!     (name: "Vacances d'Hiver", zones: ["C"], first_day: "2025-02-15", last_day: "2025-03-02"),
+//! ]
+//! ```
+//!
+//! The timetable keeps the periods of its network's zone, so that it can tell which services
+//! run on school days and which during holidays.
+
+use crate::{storage, TimeTable};
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::Display;
+
+/// Share of school days, or of holidays, a service has to run on to be told to run on them.
+const MOSTLY: f64 = 0.8;
+
+/// Share of school days, or of holidays, under which a service is told not to run on them.
+const RARELY: f64 = 0.1;
+
+/// Days without school, from `first_day` to `last_day` included.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchoolHoliday {
+    pub name: String,
+    /// Zones the period applies to, such as `["A", "B", "C"]`.
+    pub zones: Vec<String>,
+    pub first_day: NaiveDate,
+    pub last_day: NaiveDate,
+}
+
+/// School holidays of the network's zone.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HolidayCalendar {
+    pub zone: Option<String>,
+    pub school_holidays: Vec<SchoolHoliday>,
+}
+
+impl HolidayCalendar {
+    /// Reads school holidays from a RON file, keeping those of provided zone.
+    pub fn load(
+        path: impl AsRef<std::path::Path>,
+        zone: &str,
+    ) -> Result<HolidayCalendar, storage::Error> {
+        let periods: Vec<SchoolHoliday> = ron::de::from_bytes(&std::fs::read(path)?)
+            .map_err(storage::Error::RonDeserialisation)?;
+        Ok(HolidayCalendar {
+            zone: Some(zone.to_owned()),
+            school_holidays: periods
+                .into_iter()
+                .filter(|period| period.zones.iter().any(|candidate| candidate == zone))
+                .collect(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.school_holidays.is_empty()
+    }
+
+    /// School holidays the date falls in.
+    pub fn school_holiday(&self, date: NaiveDate) -> Option<&SchoolHoliday> {
+        self.school_holidays
+            .iter()
+            .find(|period| (period.first_day..=period.last_day).contains(&date))
+    }
+}
+
+/// Why a date gets holiday service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Holiday<'a> {
+    Public(&'static str),
+    School(&'a str),
+}
+
+impl Display for Holiday<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Public(name) => write!(f, "{name}"),
+            Self::School(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// French public holidays of the year, in date order.
+pub fn public_holidays(year: i32) -> Vec<(NaiveDate, &'static str)> {
+    let date = |month, day| NaiveDate::from_ymd_opt(year, month, day).expect("valid date");
+    let easter = easter_sunday(year);
+    let mut holidays = vec![
+        (date(1, 1), "Jour de l'an"),
+        (easter + chrono::Days::new(1), "Lundi de Pâques"),
+        (date(5, 1), "Fête du Travail"),
+        (date(5, 8), "Victoire 1945"),
+        (easter + chrono::Days::new(39), "Ascension"),
+        (easter + chrono::Days::new(50), "Lundi de Pentecôte"),
+        (date(7, 14), "Fête nationale"),
+        (date(8, 15), "Assomption"),
+        (date(11, 1), "Toussaint"),
+        (date(11, 11), "Armistice 1918"),
+        (date(12, 25), "Noël"),
+    ];
+    holidays.sort();
+    holidays
+}
+
+/// Name of the public holiday falling on the date, if any.
+pub fn public_holiday(date: NaiveDate) -> Option<&'static str> {
+    public_holidays(date.year())
+        .into_iter()
+        .find(|(holiday, _)| *holiday == date)
+        .map(|(_, name)| name)
+}
+
+/// Easter Sunday of the Gregorian calendar, by the anonymous Gregorian algorithm.
+fn easter_sunday(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let (b, c) = (year / 100, year % 100);
+    let (d, e) = (b / 4, b % 4);
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let (i, k) = (c / 4, c % 4);
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).expect("valid Easter date")
+}
+
+/// When a service runs, school wise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Season {
+    /// Runs on school days, not during school holidays.
+    SchoolDays,
+    /// Runs during school holidays, not on school days.
+    SchoolHolidays,
+    /// Runs on school days and during school holidays alike.
+    AllYear,
+    /// Runs on some school days or holidays only, or there is no school holiday to compare with.
+    Unknown,
+}
+
+impl Display for Season {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SchoolDays => write!(f, "school days"),
+            Self::SchoolHolidays => write!(f, "school holidays"),
+            Self::AllYear => write!(f, "all year"),
+            Self::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// How a service runs on school days and during school holidays, which tells a school days
+/// service from a holidays one whatever its id.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceSeason {
+    pub service_id: String,
+    pub season: Season,
+    /// School days the service runs on, out of those it could run on.
+    pub school_days_run: usize,
+    pub school_days: usize,
+    /// Days of school holidays the service runs on, out of those it could run on.
+    pub holidays_run: usize,
+    pub holidays: usize,
+}
+
+impl Display for ServiceSeason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}, runs on {} of {} school days and {} of {} holidays",
+            self.service_id,
+            self.season,
+            self.school_days_run,
+            self.school_days,
+            self.holidays_run,
+            self.holidays
+        )
+    }
+}
+
+impl TimeTable {
+    /// Public holiday or school holidays the date falls on, public holidays first.
+    pub fn get_holiday(&self, date: NaiveDate) -> Option<Holiday<'_>> {
+        if let Some(name) = public_holiday(date) {
+            return Some(Holiday::Public(name));
+        }
+        self.holidays
+            .school_holiday(date)
+            .map(|period| Holiday::School(&period.name))
+    }
+
+    /// Season of each service journeys run on, sorted by service id. Only days of the week the
+    /// service runs on, from its first to its last date, are counted, public holidays aside.
+    pub fn get_service_seasons(&self) -> Vec<ServiceSeason> {
+        let service_ids: BTreeSet<_> = self
+            .journeys
+            .iter()
+            .map(|journey| journey.service_id.as_str())
+            .collect();
+        service_ids
+            .into_iter()
+            .map(|service_id| self.get_service_season(service_id))
+            .collect()
+    }
+
+    fn get_service_season(&self, service_id: &str) -> ServiceSeason {
+        let dates = self.get_service_dates(service_id);
+        let weekdays: HashSet<_> = dates.iter().map(Datelike::weekday).collect();
+        let mut season = ServiceSeason {
+            service_id: service_id.to_owned(),
+            season: Season::Unknown,
+            school_days_run: 0,
+            school_days: 0,
+            holidays_run: 0,
+            holidays: 0,
+        };
+        let (Some(first), Some(last)) = (dates.first(), dates.last()) else {
+            return season;
+        };
+        for day in first.iter_days().take_while(|day| day <= last) {
+            if !weekdays.contains(&day.weekday()) || public_holiday(day).is_some() {
+                continue;
+            }
+            let runs = usize::from(dates.contains(&day));
+            if self.holidays.school_holiday(day).is_some() {
+                season.holidays += 1;
+                season.holidays_run += runs;
+            } else {
+                season.school_days += 1;
+                season.school_days_run += runs;
+            }
+        }
+        let share = |run, days| (days > 0).then(|| run as f64 / days as f64);
+        season.season = match (
+            share(season.school_days_run, season.school_days),
+            share(season.holidays_run, season.holidays),
+        ) {
+            (Some(school), Some(holidays)) if school >= MOSTLY && holidays >= MOSTLY => {
+                Season::AllYear
+            }
+            (Some(school), Some(holidays)) if school >= MOSTLY && holidays <= RARELY => {
+                Season::SchoolDays
+            }
+            (Some(school), Some(holidays)) if school <= RARELY && holidays >= MOSTLY => {
+                Season::SchoolHolidays
+            }
+            (None, Some(holidays)) if holidays >= MOSTLY => Season::SchoolHolidays,
+            _ => Season::Unknown,
+        };
+        season
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+    use crate::*;
+
+    #[test]
+    fn public() {
+        assert_eq!(easter_sunday(2024), date(2024, 3, 31));
+        assert_eq!(easter_sunday(2025), date(2025, 4, 20));
+        assert_eq!(easter_sunday(2038), date(2038, 4, 25));
+        assert_eq!(public_holiday(date(2025, 4, 21)), Some("Lundi de Pâques"));
+        assert_eq!(public_holiday(date(2025, 5, 29)), Some("Ascension"));
+        assert_eq!(public_holiday(date(2025, 6, 9)), Some("Lundi de Pentecôte"));
+        assert_eq!(public_holiday(date(2025, 6, 10)), None);
+        assert_eq!(public_holidays(2025).len(), 11);
+    }
+
+    /// A workday service for school days, another for holidays, and one all year.
+    fn sample_tt() -> TimeTable {
+        let mut tt = TimeTable::new();
+        tt.holidays = HolidayCalendar::load(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/school_holidays.ron"),
+            "C",
+        )
+        .unwrap();
+        let (first_day, last_day) = (date(2025, 1, 6), date(2025, 6, 27));
+        for service_id in ["school", "holidays", "all"] {
+            tt.service_patterns.insert(
+                service_id.to_owned(),
+                ServicePattern {
+                    weekdays: WeekdayFlags::WORKDAYS,
+                    start_date: first_day,
+                    end_date: last_day,
+                },
+            );
+            tt.journeys.push(trip(service_id, service_id, &[]));
+        }
+        for day in first_day.iter_days().take_while(|day| *day <= last_day) {
+            let exception = |exception_type| ServiceException {
+                date: day,
+                exception_type,
+            };
+            if tt.holidays.school_holiday(day).is_some() {
+                tt.exceptions
+                    .insert("school".to_owned(), exception(Exception::Deleted));
+            } else {
+                tt.exceptions
+                    .insert("holidays".to_owned(), exception(Exception::Deleted));
+            }
+        }
+        tt
+    }
+
+    #[test]
+    fn seasons() {
+        let tt = sample_tt();
+        // Only zone C periods are kept.
+        assert!(tt
+            .holidays
+            .school_holidays
+            .iter()
+            .all(|period| period.zones.contains(&"C".to_owned())));
+        assert_eq!(
+            tt.get_holiday(date(2025, 2, 18)),
+            Some(Holiday::School("Vacances d'Hiver"))
+        );
+        assert_eq!(
+            tt.get_holiday(date(2025, 4, 21)),
+            Some(Holiday::Public("Lundi de Pâques"))
+        );
+        assert_eq!(tt.get_holiday(date(2025, 3, 18)), None);
+        let seasons: Vec<_> = tt
+            .get_service_seasons()
+            .into_iter()
+            .map(|season| (season.service_id, season.season))
+            .collect();
+        assert_eq!(
+            seasons,
+            [
+                ("all".to_owned(), Season::AllYear),
+                ("holidays".to_owned(), Season::SchoolHolidays),
+                ("school".to_owned(), Season::SchoolDays),
+            ]
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! Departures as an RFC 5545 iCalendar file, for calendar apps to import or subscribe to.
+//!
+//! Each event is a single trip. Its UID is made of the trip id, service date and stops, so that
+//! importing the calendar again, or refreshing a subscription, updates events rather than
+//! duplicating them.
+
+use crate::{DatedStopTime, Route, StopSelector, TimeTable};
+use chrono::{DateTime, NaiveDate, Utc};
+
+/// Content lines longer than this many octets are folded.
+const MAX_LINE_OCTETS: usize = 75;
+
+const PRODUCT_ID: &str = "-//morningstar//timetable//EN";
+
+impl TimeTable {
+    /// Calendar of trips leaving `from` on days from `start` to `end` included, only those going
+    /// on to `to` when provided. Stops are given by exact name, id or stop area.
+    pub fn get_icalendar<'a>(
+        &'a self,
+        from: impl Into<StopSelector<'a>>,
+        to: Option<StopSelector<'a>>,
+        start: NaiveDate,
+        end: NaiveDate,
+        route_id: Option<&'a str>,
+    ) -> String {
+        let from = from.into();
+        let mut calendar = String::new();
+        let from_name = self.get_selected_name(from);
+        let name = match to {
+            Some(to) => format!("{from_name} → {}", self.get_selected_name(to)),
+            None => format!("Departures from {from_name}"),
+        };
+        for line in [
+            "BEGIN:VCALENDAR",
+            "VERSION:2.0",
+            &format!("PRODID:{PRODUCT_ID}"),
+            "CALSCALE:GREGORIAN",
+            "METHOD:PUBLISH",
+            &format!("X-WR-CALNAME:{}", escape(&name)),
+        ] {
+            push_line(&mut calendar, line);
+        }
+        for day in start.iter_days().take_while(|day| *day <= end) {
+            let trips: Vec<_> = match to {
+                Some(to) => self
+                    .get_day_stoptimes_from_a_to_b(&day, from, to, route_id)
+                    .collect(),
+                None => self
+                    .get_day_stoptimes_from_stop(&day, from, route_id)
+                    .filter_map(|board| Some((board, last_stop(board)?)))
+                    .collect(),
+            };
+            for (board, alight) in trips {
+                self.push_event(&mut calendar, board, alight);
+            }
+        }
+        push_line(&mut calendar, "END:VCALENDAR");
+        calendar
+    }
+
+    fn push_event(&self, calendar: &mut String, board: DatedStopTime, alight: DatedStopTime) {
+        let tz = self.tz();
+        let journey = board.journey.journey;
+        let route_name = self.get_route(journey).map(Route::display_name);
+        let destination = self.get_journey_destination(journey);
+        let (from, to) = (
+            self.get_stop_name(&board.stop_id),
+            self.get_stop_name(&alight.stop_id),
+        );
+        let departure = board.departure_datetime(&tz);
+        let arrival = alight.arrival_datetime(&tz);
+        let summary = match route_name {
+            Some(route_name) => format!("{route_name} to {destination}"),
+            None => format!("Bus to {destination}"),
+        };
+        let description = format!(
+            "Leaves {from} at {} towards {destination}, arrives at {to} at {}.",
+            departure.format("%H:%M"),
+            arrival.format("%H:%M")
+        );
+        let uid = format!(
+            "{}/{}/{}/{}@morningstar",
+            journey.trip_id,
+            board.journey.service_date.format("%Y%m%d"),
+            board.stop_id,
+            alight.stop_id
+        );
+        for line in [
+            "BEGIN:VEVENT".to_owned(),
+            format!("UID:{}", escape(&uid)),
+            // Stable across exports of the same timetable so that calendars don't see changes.
+            format!("DTSTAMP:{}", utc(self.extracted_on)),
+            format!("DTSTART:{}", utc(departure.with_timezone(&Utc))),
+            format!("DTEND:{}", utc(arrival.with_timezone(&Utc))),
+            format!("SUMMARY:{}", escape(&summary)),
+            format!("DESCRIPTION:{}", escape(&description)),
+            format!("LOCATION:{}", escape(from)),
+            "TRANSP:TRANSPARENT".to_owned(),
+            "END:VEVENT".to_owned(),
+        ] {
+            push_line(calendar, &line);
+        }
+    }
+}
+
+/// Last stop of the journey, when the provided one isn't it already.
+fn last_stop(board: DatedStopTime) -> Option<DatedStopTime> {
+    let journey = board.journey;
+    let last = journey.journey.stops.len().checked_sub(1)?;
+    let alight = journey.stop_at(last);
+    (!std::ptr::eq(alight.stop_time, board.stop_time)).then_some(alight)
+}
+
+fn utc(datetime: DateTime<Utc>) -> String {
+    datetime.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escapes text property values.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Appends a content line ended with CRLF, folded so that no line is longer than
+/// `MAX_LINE_OCTETS` without splitting characters.
+fn push_line(calendar: &mut String, line: &str) {
+    let mut octets = 0;
+    for char in line.chars() {
+        if octets + char.len_utf8() > MAX_LINE_OCTETS {
+            calendar.push_str("\r\n ");
+            // The leading space counts.
+            octets = 1;
+        }
+        calendar.push(char);
+        octets += char.len_utf8();
+    }
+    calendar.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+    use crate::*;
+
+    fn sample_tt() -> TimeTable {
+        let mut tt = TimeTable::new();
+        tt.timezone = "Europe/Paris".to_owned();
+        add_stops(
+            &mut tt,
+            &[("eglise", "Église"), ("marche", "Marché"), ("gare", "Gare")],
+        );
+        add_service(&mut tt, "wd1", WeekdayFlags::WORKDAYS);
+        tt.journeys.push(trip(
+            "t1",
+            "wd1",
+            &[(17, 0, "eglise"), (17, 10, "marche"), (17, 25, "gare")],
+        ));
+        tt
+    }
+
+    #[test]
+    fn events() {
+        let tt = sample_tt();
+        // Friday to Monday, with a weekend in between.
+        let (start, end) = (
+            NaiveDate::from_ymd_opt(2024, 3, 8).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 3, 11).unwrap(),
+        );
+        let calendar = tt.get_icalendar("Marché", None, start, end, None);
+        assert!(calendar.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(calendar.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(calendar.matches("BEGIN:VEVENT").count(), 2);
+        assert!(calendar.contains("UID:t1/20240308/marche/gare@morningstar\r\n"));
+        assert!(calendar.contains("DTSTART:20240308T161000Z\r\n"));
+        assert!(calendar.contains("DTEND:20240308T162500Z\r\n"));
+        assert!(calendar.contains("SUMMARY:Bus to Gare\r\n"));
+        // Same events, same UIDs.
+        assert_eq!(calendar, tt.get_icalendar("Marché", None, start, end, None));
+        // Same calendar from the stop id.
+        let marche = StopId::from("marche");
+        assert_eq!(calendar, tt.get_icalendar(&marche, None, start, end, None));
+
+        let calendar = tt.get_icalendar("Église", Some("Marché".into()), start, start, None);
+        assert!(calendar.contains("UID:t1/20240308/eglise/marche@morningstar\r\n"));
+        assert!(calendar.contains("DTEND:20240308T161000Z\r\n"));
+        // The terminus has no departures.
+        let calendar = tt.get_icalendar("Gare", None, start, end, None);
+        assert!(!calendar.contains("BEGIN:VEVENT"));
+    }
+
+    #[test]
+    fn folding() {
+        let mut calendar = String::new();
+        let line = format!("DESCRIPTION:{}", "é".repeat(60));
+        push_line(&mut calendar, &line);
+        let lines: Vec<_> = calendar.split("\r\n").collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|line| line.len() <= MAX_LINE_OCTETS));
+        assert!(lines[1].starts_with(' '));
+        assert_eq!(escape("a, b; c\\d"), "a\\, b\\; c\\\\d");
+    }
+}
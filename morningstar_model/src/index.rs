@@ -0,0 +1,173 @@
+//! Lookup tables built from a timetable, so that queries don't scan every journey and evaluate
+//! every service calendar each time.
+
+use crate::{search::SearchIndex, variant::Variant, StopId, TimeTable};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Dates whose active services are kept, a server mostly asks about today and tomorrow.
+const ACTIVE_SERVICES_CACHE_SIZE: usize = 32;
+
+/// Active services of the dates last asked about, the oldest being dropped first.
+#[derive(Default)]
+struct ActiveServices {
+    by_date: HashMap<chrono::NaiveDate, Arc<HashSet<String>>>,
+    /// Dates in the order they were added.
+    dates: VecDeque<chrono::NaiveDate>,
+}
+
+pub(crate) struct Index {
+    /// Calls at each stop as (journey, position) pairs, in timetable order.
+    postings: HashMap<StopId, Vec<(usize, usize)>>,
+    stop_ids_by_name: HashMap<String, Vec<StopId>>,
+    /// Quays of each stop area, sorted by id.
+    quay_ids_by_area: HashMap<StopId, Vec<StopId>>,
+    active_services: Mutex<ActiveServices>,
+    variants: Vec<Variant>,
+    /// Variant of each journey, by journey index.
+    journey_variants: Vec<usize>,
+    search: SearchIndex,
+}
+
+impl Index {
+    pub fn new(timetable: &TimeTable) -> Self {
+        let mut postings: HashMap<StopId, Vec<(usize, usize)>> = HashMap::new();
+        for (journey_idx, journey) in timetable.journeys.iter().enumerate() {
+            for (position, stop) in journey.stops.iter().enumerate() {
+                postings
+                    .entry(stop.stop_id.clone())
+                    .or_default()
+                    .push((journey_idx, position));
+            }
+        }
+        let mut stop_ids_by_name: HashMap<String, Vec<StopId>> = HashMap::new();
+        for stop in timetable.stops.values() {
+            stop_ids_by_name
+                .entry(stop.name.clone())
+                .or_default()
+                .push(stop.id.clone());
+        }
+        let mut quay_ids_by_area: HashMap<StopId, Vec<StopId>> = HashMap::new();
+        for stop in timetable.stops.values() {
+            if let Some(area_id) = &stop.parent_station {
+                quay_ids_by_area
+                    .entry(area_id.clone())
+                    .or_default()
+                    .push(stop.id.clone());
+            }
+        }
+        quay_ids_by_area
+            .values_mut()
+            .for_each(|quay_ids| quay_ids.sort());
+        let (variants, journey_variants) = crate::variant::detect(timetable);
+        let search = SearchIndex::new(stop_ids_by_name.keys().map(String::as_str));
+        Self {
+            postings,
+            stop_ids_by_name,
+            quay_ids_by_area,
+            active_services: Mutex::default(),
+            variants,
+            journey_variants,
+            search,
+        }
+    }
+
+    pub fn stop_ids_named(&self, stop_name: &str) -> &[StopId] {
+        self.stop_ids_by_name
+            .get(stop_name)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn quay_ids_of_area(&self, area_id: &StopId) -> &[StopId] {
+        self.quay_ids_by_area
+            .get(area_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Positions at which journeys call at any of the provided stops, by journey. Both journeys
+    /// and positions are in timetable order.
+    pub fn calls_at<'a>(
+        &self,
+        stop_ids: impl IntoIterator<Item = &'a StopId>,
+    ) -> BTreeMap<usize, Vec<usize>> {
+        let mut calls: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (journey_idx, position) in stop_ids
+            .into_iter()
+            .filter_map(|stop_id| self.postings.get(stop_id))
+            .flatten()
+        {
+            calls.entry(*journey_idx).or_default().push(*position);
+        }
+        calls.values_mut().for_each(|positions| positions.sort());
+        calls
+    }
+
+    pub fn search(&self) -> &SearchIndex {
+        &self.search
+    }
+
+    pub fn variants(&self) -> &[Variant] {
+        &self.variants
+    }
+
+    pub fn journey_variant(&self, journey_idx: usize) -> Option<&Variant> {
+        self.variants.get(*self.journey_variants.get(journey_idx)?)
+    }
+
+    /// Service ids running on provided date, computed on first request for that date.
+    pub fn active_services(
+        &self,
+        date: chrono::NaiveDate,
+        compute: impl FnOnce() -> HashSet<String>,
+    ) -> Arc<HashSet<String>> {
+        let mut cache = self
+            .active_services
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Some(services) = cache.by_date.get(&date) {
+            return services.clone();
+        }
+        if cache.dates.len() >= ACTIVE_SERVICES_CACHE_SIZE {
+            if let Some(oldest) = cache.dates.pop_front() {
+                cache.by_date.remove(&oldest);
+            }
+        }
+        let services = Arc::new(compute());
+        cache.by_date.insert(date, services.clone());
+        cache.dates.push_back(date);
+        services
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::date;
+
+    #[test]
+    fn active_services_evicts_oldest() {
+        let index = Index::new(&TimeTable::new());
+        let first_day = date(2024, 3, 1);
+        let days: Vec<_> = first_day
+            .iter_days()
+            .take(ACTIVE_SERVICES_CACHE_SIZE + 1)
+            .collect();
+        for day in &days {
+            index.active_services(*day, HashSet::new);
+        }
+        let computed = |day| {
+            let mut computed = false;
+            index.active_services(day, || {
+                computed = true;
+                HashSet::new()
+            });
+            computed
+        };
+        // Only the first day made room for the last one.
+        assert!(!computed(days[1]));
+        assert!(!computed(days[ACTIVE_SERVICES_CACHE_SIZE]));
+        assert!(computed(first_day));
+    }
+}
@@ -3,19 +3,71 @@ use std::collections::{HashMap, HashSet};
 
 use serde::{Deserialize, Serialize};
 
+mod calendar;
+pub mod diff;
+mod filter;
+mod frequency;
+pub mod holidays;
+mod icalendar;
+mod index;
+pub mod merge;
+mod migration;
+pub mod planner;
+pub mod printable;
+pub mod round_trip;
+pub mod run_times;
+pub mod search;
+mod service_time;
+pub mod stats;
+mod stop;
+pub mod storage;
+mod transfer;
+pub mod validation;
+pub mod variant;
 mod weekday_flags;
+pub use filter::DepartureFilter;
+pub use frequency::Frequency;
+pub use service_time::ServiceTime;
+pub use stop::{LocationType, Stop, StopId, StopSelector};
+pub use storage::{read_timetable, write_timetable};
+pub use transfer::{Transfer, TransferKind};
 pub use weekday_flags::WeekdayFlags;
 
+/// Days looked at when searching for next departures, enough to get past a long weekend or a
+/// bank holiday without scanning the whole calendar for stops that are no longer served.
+pub const NEXT_DEPARTURES_HORIZON_DAYS: u64 = 8;
+
+/// Days ahead of the last date of service at which users get told the timetable needs updating,
+/// published timetables usually come out a week or two before they apply.
+pub const EXPIRY_WARNING_DAYS: u64 = 14;
+
 /// Journeys and stops
+///
+/// Queries rely on lookup tables built from the timetable on first use. Callers changing the
+/// public fields of a timetable that was already queried must call `invalidate_index`, or
+/// queries keep answering from the previous journeys, stops and calendars.
 #[derive(Serialize, Deserialize)]
 pub struct TimeTable {
+    /// Layout of the model this timetable was written with, files from older layouts are
+    /// upgraded on load.
+    pub schema_version: u16,
     pub timezone: String,
+    pub routes: HashMap<String, Route>,
+    pub stops: HashMap<StopId, Stop>,
+    #[serde(default)]
+    pub transfers: Vec<Transfer>,
     pub journeys: Vec<Journey>,
-    pub excpetions: multimap::MultiMap<String, ServiceException>,
+    pub exceptions: multimap::MultiMap<String, ServiceException>,
     pub service_patterns: HashMap<String, ServicePattern>,
     pub extracted_on: chrono::DateTime<Utc>,
     pub extracted_from: String,
-    pub extracted_line_id: String,
+    pub extracted_route_ids: Vec<String>,
+    /// School holidays of the network's zone, public holidays being computed.
+    #[serde(default)]
+    pub holidays: holidays::HolidayCalendar,
+    /// Built on the first query, or by `build_index`.
+    #[serde(skip)]
+    index: std::sync::OnceLock<index::Index>,
 }
 
 impl TimeTable {
@@ -27,111 +79,411 @@ impl TimeTable {
         self.journeys
             .iter_mut()
             .map(|journey| &mut journey.stops)
-            .for_each(|stops| stops.sort_by(|lhs, rhs| lhs.time.cmp(&rhs.time)));
+            .for_each(|stops| stops.sort_by_key(|stop| (stop.arrival_time, stop.departure_time)));
         self.journeys
-            .sort_by(|lhs, rhs| lhs.stops[0].time.cmp(&rhs.stops[0].time));
+            .sort_by_key(|journey| journey.stops.first().map(|stop| stop.departure_time));
+        self.invalidate_index();
     }
 
-    /// Iterator on journeys that run on provided date.
-    pub fn get_journeys_for_day<'a>(
+    /// Build the lookup tables queries rely on now, rather than on the first query.
+    pub fn build_index(&self) {
+        self.index();
+    }
+
+    /// Drop the lookup tables, to be called after changing journeys, stops or calendars of a
+    /// timetable that was already queried.
+    pub fn invalidate_index(&mut self) {
+        self.index = std::sync::OnceLock::new();
+    }
+
+    /// Timezone of the feed, in which times of the timetable are wall clock times. Falls back to
+    /// UTC when the feed's timezone is unknown.
+    pub fn tz(&self) -> chrono_tz::Tz {
+        self.timezone.parse().unwrap_or(chrono_tz::UTC)
+    }
+
+    fn index(&self) -> &index::Index {
+        self.index.get_or_init(|| index::Index::new(self))
+    }
+
+    pub fn get_stop(&self, stop_id: &StopId) -> Option<&Stop> {
+        self.stops.get(stop_id)
+    }
+
+    /// Name of the stop, empty if it isn't in the stop table.
+    pub fn get_stop_name(&self, stop_id: &StopId) -> &str {
+        self.get_stop(stop_id)
+            .map(|stop| stop.name.as_str())
+            .unwrap_or_default()
+    }
+
+    /// Iterator on stops whose name is exactly the one provided. There can be more than one, for
+    /// instance a quay on each side of the road.
+    pub fn get_stops_named<'a>(&'a self, stop_name: &'a str) -> impl Iterator<Item = &'a Stop> {
+        self.stops
+            .values()
+            .filter(move |stop| stop.name == stop_name)
+    }
+
+    /// Iterator on quays belonging to the provided stop area.
+    pub fn get_stop_area_quays<'a>(
         &'a self,
-        day: &'a chrono::NaiveDate,
-    ) -> impl Iterator<Item = &'a Journey> {
-        self.journeys
+        stop_area_id: &'a StopId,
+    ) -> impl Iterator<Item = &'a Stop> {
+        self.stops
+            .values()
+            .filter(move |stop| stop.parent_station.as_ref() == Some(stop_area_id))
+    }
+
+    /// Stops within `radius` meters of provided position, closest first, with their distance.
+    pub fn get_stops_near(&self, latitude: f64, longitude: f64, radius: f64) -> Vec<(&Stop, f64)> {
+        let mut stops: Vec<_> = self
+            .stops
+            .values()
+            .filter_map(|stop| Some((stop, stop.distance_to(latitude, longitude)?)))
+            .filter(|(_, distance)| *distance <= radius)
+            .collect();
+        stops.sort_by(|lhs, rhs| lhs.1.total_cmp(&rhs.1));
+        stops
+    }
+
+    /// Ids of the stops with provided name, to compare stop times against.
+    fn get_stop_ids_named(&self, stop_name: &str) -> &[StopId] {
+        self.index().stop_ids_named(stop_name)
+    }
+
+    /// Ids of the stops the selector covers.
+    fn get_stop_ids<'a>(&'a self, stops: StopSelector<'a>) -> &'a [StopId] {
+        match stops {
+            StopSelector::Name(stop_name) => self.get_stop_ids_named(stop_name),
+            StopSelector::Id(stop_id) => std::slice::from_ref(stop_id),
+            StopSelector::Area(area_id) => self.index().quay_ids_of_area(area_id),
+        }
+    }
+
+    /// Name to show for the selected stops: the one given, or that of the stop or stop area.
+    fn get_selected_name<'a>(&'a self, stops: StopSelector<'a>) -> &'a str {
+        match stops {
+            StopSelector::Name(stop_name) => stop_name,
+            StopSelector::Id(stop_id) | StopSelector::Area(stop_id) => self.get_stop_name(stop_id),
+        }
+    }
+
+    /// Stops the selector covers, sorted by id, such as the quays on each side of the road that
+    /// share a name. Ids missing from the stop table are left out.
+    pub fn get_quays<'a, 's>(&'a self, stops: impl Into<StopSelector<'s>>) -> Vec<&'a Stop> {
+        let mut quays: Vec<_> = self
+            .get_stop_ids(stops.into())
             .iter()
-            .filter_map(|journey| self.filter_map_journey_on_date(journey, day))
+            .filter_map(|stop_id| self.get_stop(stop_id))
+            .collect();
+        quays.sort_by(|a, b| a.id.cmp(&b.id));
+        quays
+    }
+
+    /// Where the journey is headed: its headsign, or the name of its last stop when there is
+    /// none.
+    pub fn get_journey_destination<'a>(&'a self, journey: &'a Journey) -> &'a str {
+        journey.trip_headsign.as_deref().unwrap_or_else(|| {
+            journey
+                .stops
+                .last()
+                .map(|stop| self.get_stop_name(&stop.stop_id))
+                .expect("no journey to be empty")
+        })
     }
 
-    /// Retuns the given journey Some variant if it runs on provided day,
-    /// checking service patterns and exceptions.
-    fn filter_map_journey_on_date<'a>(
+    /// Route the journey belongs to.
+    pub fn get_route(&self, journey: &Journey) -> Option<&Route> {
+        self.routes.get(&journey.route_id)
+    }
+
+    /// Iterator on journeys that serve at least one stop on provided calendar date. This includes
+    /// journeys of the previous service day that run past midnight. Only journeys of `route_id`
+    /// are returned when it is provided.
+    pub fn get_journeys_for_day<'a>(
         &'a self,
-        journey: &'a Journey,
         day: &'a chrono::NaiveDate,
-    ) -> Option<&'a Journey> {
-        if let Some(kind) = self.get_exception_kind_for_day(&journey.service_id, day) {
-            return match kind {
-                Exception::Added => Some(journey),
-                Exception::Deleted => None,
-            };
-        }
-        let pattern = self.service_patterns.get(&journey.service_id)?;
-        if pattern.start_date.le(day)
-            && pattern.end_date.ge(day)
-            && weekday_flags::runs_on_date(day, pattern.weekdays)
-        {
-            Some(journey)
-        } else {
-            None
+        route_id: Option<&'a str>,
+    ) -> impl Iterator<Item = DatedJourney<'a>> {
+        self.journeys_serving_day(0..self.journeys.len(), *day, route_id)
+            .map(|(_, journey)| journey)
+    }
+
+    /// Journeys among the provided ones, by index, that serve at least one stop on provided
+    /// calendar date.
+    fn journeys_serving_day<'a, I>(
+        &'a self,
+        journeys: I,
+        day: chrono::NaiveDate,
+        route_id: Option<&'a str>,
+    ) -> impl Iterator<Item = (usize, DatedJourney<'a>)> + 'a
+    where
+        I: Iterator<Item = usize> + Clone + 'a,
+    {
+        let tz = self.tz();
+        let previous_day_tail = {
+            let journeys = journeys.clone();
+            day.pred_opt().into_iter().flat_map(move |previous_day| {
+                self.journeys_on_service_date(journeys.clone(), previous_day, route_id)
+                    .filter(|(_, journey)| journey.runs_past_midnight())
+            })
+        };
+        // When clocks go forward overnight, the next service day starts before midnight.
+        let next_day_head = {
+            let journeys = journeys.clone();
+            day.succ_opt()
+                .filter(|next_day| {
+                    ServiceTime::from_seconds(0)
+                        .datetime_on(*next_day, &tz)
+                        .date_naive()
+                        == day
+                })
+                .into_iter()
+                .flat_map(move |next_day| {
+                    self.journeys_on_service_date(journeys.clone(), next_day, route_id)
+                })
+        };
+        previous_day_tail
+            .chain(self.journeys_on_service_date(journeys, day, route_id))
+            .chain(next_day_head)
+            .filter(move |(_, journey)| {
+                journey
+                    .all_stops()
+                    .any(|stop| stop.departure_date(&tz) == day)
+            })
+    }
+
+    /// Iterator on journeys that run on provided service date, including stops they serve after
+    /// midnight.
+    pub fn get_journeys_for_service_date<'a>(
+        &'a self,
+        service_date: &'a chrono::NaiveDate,
+        route_id: Option<&'a str>,
+    ) -> impl Iterator<Item = DatedJourney<'a>> {
+        self.journeys_on_service_date(0..self.journeys.len(), *service_date, route_id)
+            .map(|(_, journey)| journey)
+    }
+
+    fn journeys_on_service_date<'a, I>(
+        &'a self,
+        journeys: I,
+        service_date: chrono::NaiveDate,
+        route_id: Option<&'a str>,
+    ) -> impl Iterator<Item = (usize, DatedJourney<'a>)> + 'a
+    where
+        I: Iterator<Item = usize> + 'a,
+    {
+        let active_services = self.get_services_active_on(service_date);
+        journeys
+            .map(|idx| (idx, &self.journeys[idx]))
+            .filter(move |(_, journey)| {
+                route_id.is_none_or(|route_id| journey.route_id == route_id)
+                    && active_services.contains(&journey.service_id)
+            })
+            .map(move |(idx, journey)| {
+                (
+                    idx,
+                    DatedJourney {
+                        service_date,
+                        journey,
+                    },
+                )
+            })
+    }
+
+    /// Ids of the services running on provided service date. They are cached, so serving the same
+    /// date again doesn't evaluate calendars.
+    pub fn get_services_active_on(
+        &self,
+        service_date: chrono::NaiveDate,
+    ) -> std::sync::Arc<HashSet<String>> {
+        self.index().active_services(service_date, || {
+            self.service_patterns
+                .keys()
+                .chain(self.exceptions.keys())
+                .filter(|service_id| self.service_runs_on(service_id, service_date))
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// Whether the service runs on provided day, checking service patterns and exceptions.
+    fn service_runs_on(&self, service_id: &str, day: chrono::NaiveDate) -> bool {
+        if let Some(kind) = self.get_exception_kind_for_day(service_id, &day) {
+            return kind == Exception::Added;
         }
+        self.service_patterns
+            .get(service_id)
+            .is_some_and(|pattern| {
+                pattern.start_date.le(&day)
+                    && pattern.end_date.ge(&day)
+                    && weekday_flags::runs_on_date(&day, pattern.weekdays)
+            })
+    }
+
+    /// Journeys serving provided calendar date that call at one of the selected stops, with
+    /// their index, along with the positions of these calls in the journey.
+    fn calls_on_day<'a>(
+        &'a self,
+        day: chrono::NaiveDate,
+        stops: StopSelector<'_>,
+        route_id: Option<&'a str>,
+    ) -> impl Iterator<Item = (usize, DatedJourney<'a>, Vec<usize>)> + 'a {
+        let calls = self.index().calls_at(self.get_stop_ids(stops));
+        let journeys: Vec<_> = calls.keys().copied().collect();
+        self.journeys_serving_day(journeys.into_iter(), day, route_id)
+            .map(move |(idx, journey)| (idx, journey, calls[&idx].clone()))
     }
 
     /// Iterator on stoptime tuples for stop served on provided day for a trip
-    /// in between stop a and b. Names must be exact.
+    /// in between stop a and b, given by exact name, id or stop area. The arrival at b may fall
+    /// on the next calendar day. Only trips that can be boarded at a and left at b further along
+    /// are returned.
     pub fn get_day_stoptimes_from_a_to_b<'a>(
         &'a self,
         day: &'a chrono::NaiveDate,
-        a: &'a str,
-        b: &'a str,
-    ) -> impl Iterator<Item = (&'a StopTime, &'a StopTime)> {
-        let today_journeys = self.get_journeys_for_day(day);
-        today_journeys.filter_map(move |journey| {
-            match (
-                journey.stops.iter().find(|stop| stop.stop_name == a),
-                journey.stops.iter().find(|stop| stop.stop_name == b),
-            ) {
-                (Some(stop1), Some(stop2)) if stop1.time < stop2.time => Some((stop1, stop2)),
-                _ => None,
-            }
-        })
+        a: impl Into<StopSelector<'a>>,
+        b: impl Into<StopSelector<'a>>,
+        route_id: Option<&'a str>,
+    ) -> impl Iterator<Item = (DatedStopTime<'a>, DatedStopTime<'a>)> {
+        let day = *day;
+        let tz = self.tz();
+        let a_calls = self.index().calls_at(self.get_stop_ids(a.into()));
+        let b_calls = self.index().calls_at(self.get_stop_ids(b.into()));
+        let journeys: Vec<_> = a_calls
+            .keys()
+            .filter(|idx| b_calls.contains_key(idx))
+            .copied()
+            .collect();
+        self.journeys_serving_day(journeys.into_iter(), day, route_id)
+            .filter_map(move |(idx, journey)| {
+                let (a_positions, b_positions) = (&a_calls[&idx], &b_calls[&idx]);
+                a_positions.iter().find_map(|a_position| {
+                    let stop1 = journey.stop_at(*a_position);
+                    if stop1.departure_date(&tz) != day || !stop1.can_board() {
+                        return None;
+                    }
+                    // Loop journeys can call at b before a as well as after.
+                    let stop2 = b_positions
+                        .iter()
+                        .filter(|b_position| *b_position > a_position)
+                        .map(|b_position| journey.stop_at(*b_position))
+                        .find(|stop| stop.can_alight())?;
+                    Some((stop1, stop2))
+                })
+            })
     }
 
     /// Iterator on stoptimes for stop served on provided day for a trip
-    /// from a stop name. Names must be exact.
+    /// from a stop given by exact name, id or stop area. Stops where boarding isn't possible are
+    /// left out.
     pub fn get_day_stoptimes_from_stop<'a>(
         &'a self,
         day: &'a chrono::NaiveDate,
-        stop_name: &'a str,
-    ) -> impl Iterator<Item = &'a StopTime> {
-        let today_journeys = self.get_journeys_for_day(day);
-        today_journeys.filter_map(move |journey| {
-            journey
-                .stops
-                .iter()
-                .find(|stop| stop.stop_name == stop_name)
-        })
+        stop: impl Into<StopSelector<'a>>,
+        route_id: Option<&'a str>,
+    ) -> impl Iterator<Item = DatedStopTime<'a>> {
+        let day = *day;
+        let tz = self.tz();
+        self.calls_on_day(day, stop.into(), route_id)
+            .filter_map(move |(_, journey, positions)| {
+                positions
+                    .into_iter()
+                    .map(|position| journey.stop_at(position))
+                    .find(|stop| stop.departure_date(&tz) == day && stop.can_board())
+            })
     }
 
-    pub fn get_day_stoptimes_and_destination_for_stop<'a>(
+    /// Iterator on departures from a stop on provided day, along with where they're headed. The
+    /// stop is given by exact name, id or stop area. Journeys can't be boarded at their terminus
+    /// or where pickup isn't available, so these are left out, as are those the filter doesn't
+    /// keep.
+    pub fn get_day_stoptimes_and_destination_for_stop<'a, S: Into<StopSelector<'a>>>(
         &'a self,
         day: &'a chrono::NaiveDate,
-        stop_name: &'a str,
-    ) -> impl Iterator<Item = StopTimeWithDestination> + use<'a> {
-        self.get_journeys_for_day(day).flat_map(move |journey| {
-            let destination = journey
-                .stops
-                .last()
-                .map(|stop| stop.stop_name.as_str())
-                .expect("no journey to be empty");
-            let stops_len = journey.stops.len();
-            journey
-                .stops
-                .iter()
-                .enumerate()
-                .filter(move |(idx, stop)| *idx != stops_len && stop.stop_name == stop_name)
-                .map(move |(idx, stop)| StopTimeWithDestination {
-                    stop_name: stop.stop_name.clone(),
-                    stop_id: stop.stop_id.clone(),
-                    time: stop.time.clone(),
-                    destination: destination.to_string(),
-                    stops_to_destination: (stops_len - idx) as u32 - 1,
-                })
-        })
+        stop: S,
+        route_id: Option<&'a str>,
+        filter: DepartureFilter<'a>,
+    ) -> impl Iterator<Item = StopTimeWithDestination> + use<'a, S> {
+        let day = *day;
+        let tz = self.tz();
+        let filter = self.prepare_filter(filter);
+        self.calls_on_day(day, stop.into(), route_id).flat_map(
+            move |(journey_idx, journey, positions)| {
+                let route = self.get_route(&journey);
+                let destination = self.get_journey_destination(journey.journey);
+                let variant = self.get_journey_variant(journey_idx);
+                let stops_len = journey.stops.len();
+                positions
+                    .into_iter()
+                    .filter(|idx| *idx + 1 != stops_len)
+                    .filter(|idx| filter.keeps(self, journey_idx, journey.journey, *idx))
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .map(move |idx| (idx, journey.stop_at(idx)))
+                    .filter(move |(_, stop)| stop.can_board() && stop.departure_date(&tz) == day)
+                    .map(move |(idx, stop)| StopTimeWithDestination {
+                        stop_name: self.get_stop_name(&stop.stop_id).to_owned(),
+                        stop_id: stop.stop_id.clone(),
+                        time: stop.departure_time_of_day(&tz),
+                        departure: stop.departure_datetime(&tz).fixed_offset(),
+                        service_date: journey.service_date,
+                        exact_time: journey.has_exact_times(),
+                        route_id: journey.route_id.clone(),
+                        route_name: route.map(Route::display_name).map(str::to_owned),
+                        trip_id: journey.trip_id.clone(),
+                        direction_id: journey.direction_id,
+                        destination: destination.to_string(),
+                        stops_to_destination: (stops_len - idx) as u32 - 1,
+                        variant: variant
+                            .map(|variant| variant.label.clone())
+                            .unwrap_or_default(),
+                        main_variant: variant.is_none_or(|variant| variant.main),
+                        holiday: self.get_holiday(day).map(|holiday| holiday.to_string()),
+                    })
+            },
+        )
     }
 
-    pub fn get_stops_served_on_day<'a>(&'a self, day: &'a chrono::NaiveDate) -> HashSet<&'a str> {
-        self.get_journeys_for_day(day)
-            .flat_map(|journey| journey.stops.iter().map(|stop| stop.stop_name.as_str()))
+    /// Iterator on the departures from a stop at or after provided instant, soonest first, along
+    /// with where they're headed. Days are searched as needed, up to
+    /// `NEXT_DEPARTURES_HORIZON_DAYS` after the one `after` falls on in the feed's timezone.
+    pub fn get_next_departures_from_stop<'a, Tz: TimeZone, S: Into<StopSelector<'a>>>(
+        &'a self,
+        after: &DateTime<Tz>,
+        stop: S,
+        route_id: Option<&'a str>,
+        filter: DepartureFilter<'a>,
+    ) -> impl Iterator<Item = StopTimeWithDestination> + use<'a, Tz, S> {
+        let after = after.fixed_offset();
+        let stop = stop.into();
+        let first_day = after.with_timezone(&self.tz()).date_naive();
+        first_day
+            .iter_days()
+            .take(NEXT_DEPARTURES_HORIZON_DAYS as usize)
+            .flat_map(move |day| {
+                let mut departures: Vec<_> = self
+                    .get_day_stoptimes_and_destination_for_stop(&day, stop, route_id, filter)
+                    .filter(|departure| departure.departure >= after)
+                    .collect();
+                departures.sort_by_key(|departure| departure.departure);
+                departures
+            })
+    }
+
+    pub fn get_stops_served_on_day<'a>(
+        &'a self,
+        day: &'a chrono::NaiveDate,
+        route_id: Option<&'a str>,
+    ) -> HashSet<&'a str> {
+        let tz = self.tz();
+        self.get_journeys_for_day(day, route_id)
+            .flat_map(|journey| {
+                journey
+                    .stops_on(*day, tz)
+                    .map(|stop| self.get_stop_name(&stop.stop_time.stop_id))
+            })
             .collect()
     }
 
@@ -141,12 +493,12 @@ impl TimeTable {
         day: &chrono::NaiveDate,
     ) -> Option<Exception> {
         let (added, deleted) = self
-            .excpetions
+            .exceptions
             .get_vec(service_id)?
             .iter()
             .filter(|exception| exception.date == *day)
-            .fold((false, false), |acc, excpetion| {
-                match excpetion.exception_type {
+            .fold((false, false), |acc, exception| {
+                match exception.exception_type {
                     Exception::Added => (true, acc.1),
                     Exception::Deleted => (acc.0, true),
                 }
@@ -165,39 +517,249 @@ impl Default for TimeTable {
     fn default() -> Self {
         let now = Utc::now();
         Self {
+            schema_version: storage::SCHEMA_VERSION,
             timezone: "Europe/Paris".to_string(),
+            routes: HashMap::new(),
+            stops: HashMap::new(),
+            transfers: vec![],
             journeys: vec![],
-            excpetions: multimap::MultiMap::new(),
+            exceptions: multimap::MultiMap::new(),
             service_patterns: HashMap::new(),
             extracted_on: now,
             extracted_from: String::new(),
-            extracted_line_id: String::new(),
+            extracted_route_ids: vec![],
+            holidays: holidays::HolidayCalendar::default(),
+            index: std::sync::OnceLock::new(),
         }
     }
 }
 
+/// Bus line, as described by GTFS routes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Route {
+    pub id: String,
+    pub short_name: Option<String>,
+    pub long_name: Option<String>,
+    /// Hexadecimal RGB colour, without leading `#`, `None` when the feed gives none or gives
+    /// the GTFS default of white.
+    pub color: Option<String>,
+    /// Hexadecimal RGB colour, without leading `#`, `None` when the feed gives none or gives
+    /// the GTFS default of black.
+    pub text_color: Option<String>,
+    pub agency_id: Option<String>,
+    pub agency_name: Option<String>,
+}
+
+impl Route {
+    /// Name the line is known by to its users: its short name when it has one.
+    pub fn display_name(&self) -> &str {
+        self.short_name
+            .as_deref()
+            .or(self.long_name.as_deref())
+            .unwrap_or(&self.id)
+    }
+}
+
 /// One bus journey, with all its stops and bitflags indicating when does
 /// it run.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Journey {
+    pub trip_id: String,
+    pub route_id: String,
     pub service_id: String,
+    /// Destination shown on the bus, as published by the operator.
+    pub trip_headsign: Option<String>,
+    /// Trip name shown to passengers, such as a train number.
+    pub trip_short_name: Option<String>,
+    pub direction_id: Option<Direction>,
+    /// Journeys the same vehicle makes one after the other share a block.
+    pub block_id: Option<String>,
     pub stops: Vec<StopTime>,
+    /// Frequency this journey was generated from, when it comes from a headway-based trip.
+    #[serde(default)]
+    pub frequency: Option<Frequency>,
+}
+
+impl Journey {
+    /// Whether some stops of this journey are served after midnight of its service day.
+    pub fn runs_past_midnight(&self) -> bool {
+        self.stops.iter().any(|stop| {
+            stop.arrival_time
+                .max(stop.departure_time)
+                .days_after_service_date()
+                > 0
+        })
+    }
+}
+
+/// Way a journey travels along its route, mirroring GTFS `direction_id`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Direction {
+    Outbound,
+    Inbound,
+}
+
+/// A journey along with the service date it runs on, needed to know on which calendar day each
+/// of its stops is served.
+#[derive(Debug, Clone, Copy)]
+pub struct DatedJourney<'a> {
+    pub service_date: chrono::NaiveDate,
+    pub journey: &'a Journey,
+}
+
+impl<'a> DatedJourney<'a> {
+    /// Iterator on every stop of the journey.
+    pub fn all_stops(&self) -> impl Iterator<Item = DatedStopTime<'a>> + use<'a> {
+        let dated = *self;
+        self.journey
+            .stops
+            .iter()
+            .map(move |stop_time| DatedStopTime {
+                journey: dated,
+                stop_time,
+            })
+    }
+
+    /// Stop time at provided position in the journey.
+    pub fn stop_at(&self, position: usize) -> DatedStopTime<'a> {
+        DatedStopTime {
+            journey: *self,
+            stop_time: &self.journey.stops[position],
+        }
+    }
+
+    /// Iterator on the stops of the journey the bus leaves on provided calendar day, in the
+    /// provided timezone which should be the feed's.
+    pub fn stops_on<Tz: TimeZone + 'a>(
+        &self,
+        day: chrono::NaiveDate,
+        tz: Tz,
+    ) -> impl Iterator<Item = DatedStopTime<'a>> + use<'a, Tz> {
+        self.all_stops()
+            .filter(move |stop| stop.departure_date(&tz) == day)
+    }
+}
+
+impl std::ops::Deref for DatedJourney<'_> {
+    type Target = Journey;
+
+    fn deref(&self) -> &Self::Target {
+        self.journey
+    }
+}
+
+/// A stop time of a journey running on a known service date.
+#[derive(Debug, Clone, Copy)]
+pub struct DatedStopTime<'a> {
+    pub journey: DatedJourney<'a>,
+    pub stop_time: &'a StopTime,
+}
+
+impl DatedStopTime<'_> {
+    /// Calendar date on which the bus arrives at the stop, in the provided timezone which should
+    /// be the feed's.
+    pub fn arrival_date<Tz: TimeZone>(&self, tz: &Tz) -> chrono::NaiveDate {
+        self.arrival_datetime(tz).date_naive()
+    }
+
+    /// Wall clock time at which the bus arrives, on the day returned by `arrival_date`.
+    pub fn arrival_time_of_day<Tz: TimeZone>(&self, tz: &Tz) -> chrono::NaiveTime {
+        self.arrival_datetime(tz).time()
+    }
+
+    /// Calendar date on which the bus leaves the stop, in the provided timezone which should be
+    /// the feed's.
+    pub fn departure_date<Tz: TimeZone>(&self, tz: &Tz) -> chrono::NaiveDate {
+        self.departure_datetime(tz).date_naive()
+    }
+
+    /// Wall clock time at which the bus leaves, on the day returned by `departure_date`.
+    pub fn departure_time_of_day<Tz: TimeZone>(&self, tz: &Tz) -> chrono::NaiveTime {
+        self.departure_datetime(tz).time()
+    }
+
+    /// Instant at which the bus arrives, in the provided timezone which should be the feed's.
+    pub fn arrival_datetime<Tz: TimeZone>(&self, tz: &Tz) -> DateTime<Tz> {
+        self.stop_time
+            .arrival_time
+            .datetime_on(self.journey.service_date, tz)
+    }
+
+    /// Instant at which the bus leaves, in the provided timezone which should be the feed's.
+    pub fn departure_datetime<Tz: TimeZone>(&self, tz: &Tz) -> DateTime<Tz> {
+        self.stop_time
+            .departure_time
+            .datetime_on(self.journey.service_date, tz)
+    }
+}
+
+impl std::ops::Deref for DatedStopTime<'_> {
+    type Target = StopTime;
+
+    fn deref(&self) -> &Self::Target {
+        self.stop_time
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct StopTime {
-    pub time: chrono::NaiveTime,
-    pub stop_name: String,
-    pub stop_id: String,
+    pub arrival_time: ServiceTime,
+    /// Later than arrival when the bus waits at the stop, at timing points for instance.
+    pub departure_time: ServiceTime,
+    pub stop_id: StopId,
+    pub pickup_type: PickupDropOff,
+    pub drop_off_type: PickupDropOff,
+}
+
+impl StopTime {
+    /// Whether passengers can get on the bus at this stop.
+    pub fn can_board(&self) -> bool {
+        self.pickup_type != PickupDropOff::NotAvailable
+    }
+
+    /// Whether passengers can get off the bus at this stop.
+    pub fn can_alight(&self) -> bool {
+        self.drop_off_type != PickupDropOff::NotAvailable
+    }
+}
+
+/// Whether and how passengers can get on or off at a stop, mirroring GTFS `pickup_type` and
+/// `drop_off_type`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum PickupDropOff {
+    #[default]
+    Regular,
+    NotAvailable,
+    ArrangeByPhone,
+    CoordinateWithDriver,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct StopTimeWithDestination {
+    /// Wall clock departure time on the queried calendar day.
     pub time: chrono::NaiveTime,
+    /// Departure in the feed's timezone.
+    pub departure: chrono::DateTime<FixedOffset>,
+    /// Day the journey's service runs on, the day before `departure` for buses after midnight.
+    pub service_date: chrono::NaiveDate,
+    /// Whether `departure` is kept by the journey rather than estimated from a headway.
+    pub exact_time: bool,
     pub stop_name: String,
-    pub stop_id: String,
+    pub stop_id: StopId,
+    pub route_id: String,
+    pub route_name: Option<String>,
+    pub trip_id: String,
+    pub direction_id: Option<Direction>,
+    /// Headsign of the journey, or its last stop when it has none.
     pub destination: String,
     pub stops_to_destination: u32,
+    /// Label of the stop pattern the journey follows, such as "via Potato Factory".
+    pub variant: String,
+    /// Whether the journey follows the main stop pattern of its route.
+    pub main_variant: bool,
+    /// Public holiday or school holidays the departure falls on, when the bus likely runs a
+    /// holiday service.
+    pub holiday: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -225,13 +787,107 @@ pub enum Exception {
     Deleted,
 }
 
+/// Fixtures shared by the tests of every module.
+#[cfg(test)]
+pub(crate) mod test_util {
+    use super::*;
+
+    /// Stop time where the bus arrives and leaves at the same minute.
+    pub(crate) fn stop_time(hour: u32, minute: u32, stop_id: &str) -> StopTime {
+        StopTime {
+            arrival_time: ServiceTime::from_hms(hour, minute, 0),
+            departure_time: ServiceTime::from_hms(hour, minute, 0),
+            stop_id: stop_id.into(),
+            pickup_type: PickupDropOff::Regular,
+            drop_off_type: PickupDropOff::Regular,
+        }
+    }
+
+    /// Journey with a trip id made of its service and first departure.
+    pub(crate) fn journey(route_id: &str, service_id: &str, stops: Vec<StopTime>) -> Journey {
+        Journey {
+            trip_id: format!("{service_id}-{}", stops[0].departure_time),
+            route_id: route_id.to_owned(),
+            service_id: service_id.to_owned(),
+            trip_headsign: None,
+            trip_short_name: None,
+            direction_id: None,
+            block_id: None,
+            stops,
+            frequency: None,
+        }
+    }
+
+    /// Journey of route `r1` calling at each `(hour, minute, stop_id)` in turn.
+    pub(crate) fn trip(trip_id: &str, service_id: &str, stops: &[(u32, u32, &str)]) -> Journey {
+        Journey {
+            trip_id: trip_id.to_owned(),
+            route_id: "r1".to_owned(),
+            service_id: service_id.to_owned(),
+            trip_headsign: None,
+            trip_short_name: None,
+            direction_id: None,
+            block_id: None,
+            stops: stops
+                .iter()
+                .map(|(hour, minute, stop_id)| stop_time(*hour, *minute, stop_id))
+                .collect(),
+            frequency: None,
+        }
+    }
+
+    pub(crate) fn stop(id: &str, name: &str, parent_station: Option<&str>) -> Stop {
+        Stop {
+            id: id.into(),
+            name: name.to_owned(),
+            latitude: None,
+            longitude: None,
+            parent_station: parent_station.map(StopId::from),
+            location_type: LocationType::StopPoint,
+            platform_code: None,
+        }
+    }
+
+    /// Adds stops given by id and name, without a parent station.
+    pub(crate) fn add_stops(tt: &mut TimeTable, stops: &[(&str, &str)]) {
+        for (id, name) in stops {
+            tt.stops.insert((*id).into(), stop(id, name, None));
+        }
+    }
+
+    /// Adds a service running on provided weekdays all through 2024.
+    pub(crate) fn add_service(tt: &mut TimeTable, service_id: &str, weekdays: WeekdayFlags) {
+        tt.service_patterns.insert(
+            service_id.to_owned(),
+            ServicePattern {
+                weekdays,
+                start_date: date(2024, 1, 1),
+                end_date: date(2024, 12, 31),
+            },
+        );
+    }
+
+    pub(crate) fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use super::test_util::*;
     use super::*;
-    use chrono::prelude::*;
 
     fn sample_tt() -> TimeTable {
         let mut tt = super::TimeTable::new();
+        for stop in [
+            stop("eglise", "Église", None),
+            stop("marche", "Marché", None),
+            stop("potato", "Potato Factory", None),
+            stop("gare", "Gare", None),
+            stop("airsoft", "Terrain d'airsoft", None),
+        ] {
+            tt.stops.insert(stop.id.clone(), stop);
+        }
         let mut service_pattern = super::ServicePattern {
             weekdays: WeekdayFlags::WORKDAYS,
             start_date: NaiveDate::from_yo_opt(2024, 1).unwrap(),
@@ -239,77 +895,45 @@ mod test {
         };
         tt.service_patterns
             .insert("wd1".to_owned(), service_pattern.clone());
+        tt.routes.insert(
+            "r1".to_owned(),
+            Route {
+                id: "r1".to_owned(),
+                short_name: Some("2245".to_owned()),
+                long_name: Some("Église - Gare".to_owned()),
+                color: Some("FF0000".to_owned()),
+                text_color: Some("FFFFFF".to_owned()),
+                agency_id: None,
+                agency_name: None,
+            },
+        );
         service_pattern.weekdays = WeekdayFlags::WEEKENDS;
         tt.service_patterns
             .insert("we1".to_owned(), service_pattern.clone());
         let mut wd_stops = vec![
-            StopTime {
-                time: chrono::NaiveTime::from_hms_opt(14, 0, 0).unwrap(),
-                stop_name: "Église".to_owned(),
-                stop_id: "".to_owned(),
-            },
-            StopTime {
-                time: chrono::NaiveTime::from_hms_opt(14, 6, 0).unwrap(),
-                stop_name: "Marché".to_owned(),
-                stop_id: "".to_owned(),
-            },
-            StopTime {
-                time: chrono::NaiveTime::from_hms_opt(14, 9, 0).unwrap(),
-                stop_name: "Potato Factory".to_owned(),
-                stop_id: "".to_owned(),
-            },
-            StopTime {
-                time: chrono::NaiveTime::from_hms_opt(14, 15, 0).unwrap(),
-                stop_name: "Gare".to_owned(),
-                stop_id: "".to_owned(),
-            },
+            stop_time(14, 0, "eglise"),
+            stop_time(14, 6, "marche"),
+            stop_time(14, 9, "potato"),
+            stop_time(14, 15, "gare"),
         ];
-        tt.journeys.push(Journey {
-            service_id: "wd1".to_owned(),
-            stops: wd_stops.clone(),
-        });
-        wd_stops[0].time = chrono::NaiveTime::from_hms_opt(15, 0, 0).unwrap();
-        wd_stops[1].time = chrono::NaiveTime::from_hms_opt(15, 6, 0).unwrap();
-        wd_stops[2].time = chrono::NaiveTime::from_hms_opt(15, 6, 0).unwrap();
-        wd_stops[3].time = chrono::NaiveTime::from_hms_opt(15, 15, 0).unwrap();
-        tt.journeys.push(Journey {
-            service_id: "wd1".to_owned(),
-            stops: wd_stops.clone(),
-        });
+        tt.journeys.push(journey("r1", "wd1", wd_stops.clone()));
+        wd_stops[0] = stop_time(15, 0, "eglise");
+        wd_stops[1] = stop_time(15, 6, "marche");
+        wd_stops[2] = stop_time(15, 6, "potato");
+        wd_stops[3] = stop_time(15, 15, "gare");
+        tt.journeys.push(journey("r1", "wd1", wd_stops.clone()));
         let mut we_stops = vec![
-            StopTime {
-                time: chrono::NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
-                stop_name: "Église".to_owned(),
-                stop_id: "".to_owned(),
-            },
-            StopTime {
-                time: chrono::NaiveTime::from_hms_opt(16, 6, 0).unwrap(),
-                stop_name: "Marché".to_owned(),
-                stop_id: "".to_owned(),
-            },
-            StopTime {
-                time: chrono::NaiveTime::from_hms_opt(16, 9, 0).unwrap(),
-                stop_name: "Terrain d'airsoft".to_owned(),
-                stop_id: "".to_owned(),
-            },
-            StopTime {
-                time: chrono::NaiveTime::from_hms_opt(16, 15, 0).unwrap(),
-                stop_name: "Gare".to_owned(),
-                stop_id: "".to_owned(),
-            },
+            stop_time(16, 0, "eglise"),
+            stop_time(16, 6, "marche"),
+            stop_time(16, 9, "airsoft"),
+            stop_time(16, 15, "gare"),
         ];
-        tt.journeys.push(Journey {
-            service_id: "we1".to_owned(),
-            stops: we_stops.clone(),
-        });
-        we_stops[0].time = chrono::NaiveTime::from_hms_opt(15, 0, 0).unwrap();
-        we_stops[1].time = chrono::NaiveTime::from_hms_opt(15, 6, 0).unwrap();
-        we_stops[2].time = chrono::NaiveTime::from_hms_opt(15, 6, 0).unwrap();
-        we_stops[3].time = chrono::NaiveTime::from_hms_opt(15, 15, 0).unwrap();
-        tt.journeys.push(Journey {
-            service_id: "we1".to_owned(),
-            stops: we_stops.clone(),
-        });
+        tt.journeys.push(journey("r1", "we1", we_stops.clone()));
+        we_stops[0] = stop_time(15, 0, "eglise");
+        we_stops[1] = stop_time(15, 6, "marche");
+        we_stops[2] = stop_time(15, 6, "airsoft");
+        we_stops[3] = stop_time(15, 15, "gare");
+        tt.journeys.push(journey("r1", "we1", we_stops.clone()));
         tt
     }
 
@@ -322,23 +946,21 @@ mod test {
     fn internal_inside_operating_range(tt: &mut TimeTable) {
         let we_day = NaiveDate::from_yo_opt(2024, 7).unwrap();
         let wd_day = NaiveDate::from_yo_opt(2024, 8).unwrap();
-        let journeys: Vec<_> = tt.get_journeys_for_day(&wd_day).collect();
+        let journeys: Vec<_> = tt.get_journeys_for_day(&wd_day, None).collect();
         for item in journeys {
             let count = item
                 .stops
                 .iter()
-                .map(|stop_time| &stop_time.stop_name)
-                .filter(|stop_name| *stop_name == "Potato Factory")
+                .filter(|stop_time| stop_time.stop_id.as_str() == "potato")
                 .count();
             assert_eq!(count, 1);
         }
-        let journeys = tt.get_journeys_for_day(&we_day);
+        let journeys = tt.get_journeys_for_day(&we_day, None);
         for item in journeys {
             let count = item
                 .stops
                 .iter()
-                .map(|stop_time| &stop_time.stop_name)
-                .filter(|stop_name| *stop_name == "Terrain d'airsoft")
+                .filter(|stop_time| stop_time.stop_id.as_str() == "airsoft")
                 .count();
             assert_eq!(count, 1);
         }
@@ -353,12 +975,12 @@ mod test {
     fn internal_outside_operating_range(tt: &mut TimeTable) {
         let we_day = NaiveDate::from_yo_opt(2024, 222).unwrap();
         let wd_day = NaiveDate::from_yo_opt(2024, 223).unwrap();
-        let journeys = tt.get_journeys_for_day(&wd_day).count();
+        let journeys = tt.get_journeys_for_day(&wd_day, None).count();
         assert_eq!(journeys, 0);
-        let journeys = tt.get_journeys_for_day(&we_day).count();
+        let journeys = tt.get_journeys_for_day(&we_day, None).count();
         assert_eq!(journeys, 0);
         let day = NaiveDate::from_yo_opt(2024, 76).unwrap();
-        let journeys = tt.get_journeys_for_day(&day).count();
+        let journeys = tt.get_journeys_for_day(&day, None).count();
         assert_eq!(journeys, 0);
     }
 
@@ -378,13 +1000,13 @@ mod test {
         let tt = sample_tt();
         let day = NaiveDate::from_yo_opt(2024, 7).unwrap();
         let stoptimes: Vec<_> = tt
-            .get_day_stoptimes_from_a_to_b(&day, "Marché", "Gare")
+            .get_day_stoptimes_from_a_to_b(&day, "Marché", "Gare", None)
             .collect();
         let size = stoptimes.len();
         assert_eq!(size, 2);
         for stoptime in stoptimes {
-            assert_eq!(stoptime.0.stop_name, "Marché");
-            assert_eq!(stoptime.1.stop_name, "Gare");
+            assert_eq!(tt.get_stop_name(&stoptime.0.stop_id), "Marché");
+            assert_eq!(tt.get_stop_name(&stoptime.1.stop_id), "Gare");
         }
     }
 
@@ -393,7 +1015,7 @@ mod test {
         let tt = sample_tt();
         let day = NaiveDate::from_yo_opt(2024, 7).unwrap();
         let stoptimes: Vec<_> = tt
-            .get_day_stoptimes_from_a_to_b(&day, "Gare", "Marché")
+            .get_day_stoptimes_from_a_to_b(&day, "Gare", "Marché", None)
             .collect();
         let size = stoptimes.len();
         assert_eq!(size, 0);
@@ -404,7 +1026,7 @@ mod test {
         let tt = sample_tt();
         let day = NaiveDate::from_yo_opt(2024, 7).unwrap();
         let stoptimes: Vec<_> = tt
-            .get_day_stoptimes_from_a_to_b(&day, "Marché", "Arrêt qui n'existe pas")
+            .get_day_stoptimes_from_a_to_b(&day, "Marché", "Arrêt qui n'existe pas", None)
             .collect();
         let size = stoptimes.len();
         assert_eq!(size, 0);
@@ -415,26 +1037,535 @@ mod test {
         let tt = sample_tt();
         let day = NaiveDate::from_yo_opt(2024, 7).unwrap();
         let stoptimes: Vec<_> = tt
-            .get_day_stoptimes_from_a_to_b(&day, "Arrêt qui n'existe pas", "Marché")
+            .get_day_stoptimes_from_a_to_b(&day, "Arrêt qui n'existe pas", "Marché", None)
             .collect();
         let size = stoptimes.len();
         assert_eq!(size, 0);
     }
 
+    #[test]
+    fn a_to_b_loop_journey() {
+        let mut tt = TimeTable::new();
+        add_stops(&mut tt, &[("gare", "Gare"), ("eglise", "Église")]);
+        add_service(&mut tt, "wd1", WeekdayFlags::WORKDAYS);
+        tt.journeys.push(trip(
+            "loop",
+            "wd1",
+            &[(8, 0, "gare"), (8, 10, "eglise"), (8, 20, "gare")],
+        ));
+        let day = date(2024, 3, 4);
+        let times = |a, b| -> Vec<_> {
+            tt.get_day_stoptimes_from_a_to_b(&day, a, b, None)
+                .map(|(from, to)| (from.departure_time, to.arrival_time))
+                .collect()
+        };
+        assert_eq!(
+            times("Église", "Gare"),
+            [(
+                ServiceTime::from_hms(8, 10, 0),
+                ServiceTime::from_hms(8, 20, 0)
+            )]
+        );
+        assert_eq!(
+            times("Gare", "Église"),
+            [(
+                ServiceTime::from_hms(8, 0, 0),
+                ServiceTime::from_hms(8, 10, 0)
+            )]
+        );
+    }
+
     #[test]
     fn day_stop_names() {
         let tt = sample_tt();
         let day = NaiveDate::from_yo_opt(2024, 7).unwrap();
-        let stop_names = tt.get_stops_served_on_day(&day);
+        let stop_names = tt.get_stops_served_on_day(&day, None);
         assert_eq!(
             stop_names,
             ["Église", "Marché", "Gare", "Terrain d'airsoft"].into()
         );
         let day = NaiveDate::from_yo_opt(2024, 8).unwrap();
-        let stop_names = tt.get_stops_served_on_day(&day);
+        let stop_names = tt.get_stops_served_on_day(&day, None);
         assert_eq!(
             stop_names,
             ["Église", "Marché", "Gare", "Potato Factory"].into()
         );
     }
+
+    fn add_night_journey(tt: &mut TimeTable) {
+        let stops = [(23, 50, "eglise"), (24, 5, "marche"), (24, 20, "gare")]
+            .into_iter()
+            .map(|(hour, minute, stop_id)| stop_time(hour, minute, stop_id))
+            .collect();
+        tt.journeys.push(journey("r1", "wd1", stops));
+    }
+
+    #[test]
+    fn past_midnight_tail() {
+        let mut tt = sample_tt();
+        add_night_journey(&mut tt);
+        tt.sort_journeys_and_stops();
+        // Friday service runs into Saturday morning.
+        let friday = NaiveDate::from_ymd_opt(2024, 1, 12).unwrap();
+        let saturday = friday.succ_opt().unwrap();
+        let stoptimes: Vec<_> = tt
+            .get_day_stoptimes_from_stop(&saturday, "Marché", None)
+            .collect();
+        assert_eq!(stoptimes.len(), 3);
+        assert_eq!(stoptimes[0].journey.service_date, friday);
+        assert_eq!(stoptimes[0].departure_date(&tt.tz()), saturday);
+        assert_eq!(
+            stoptimes[0].departure_time_of_day(&tt.tz()),
+            NaiveTime::from_hms_opt(0, 5, 0).unwrap()
+        );
+        // On friday, Marché is served by thursday's night journey rather than friday's.
+        let stoptimes: Vec<_> = tt
+            .get_day_stoptimes_from_stop(&friday, "Marché", None)
+            .collect();
+        assert_eq!(stoptimes.len(), 3);
+        assert_eq!(
+            stoptimes[0].journey.service_date,
+            friday.pred_opt().unwrap()
+        );
+        let count = tt
+            .get_day_stoptimes_from_stop(&friday, "Église", None)
+            .count();
+        assert_eq!(count, 3);
+        let stoptimes: Vec<_> = tt
+            .get_day_stoptimes_from_a_to_b(&friday, "Église", "Gare", None)
+            .collect();
+        assert_eq!(stoptimes.len(), 3);
+        assert_eq!(stoptimes[2].1.arrival_date(&tt.tz()), saturday);
+        // No saturday service on sunday morning.
+        let sunday = saturday.succ_opt().unwrap();
+        let count = tt
+            .get_day_stoptimes_and_destination_for_stop(
+                &sunday,
+                "Gare",
+                None,
+                DepartureFilter::default(),
+            )
+            .filter(|stop| stop.time < NaiveTime::from_hms_opt(4, 0, 0).unwrap())
+            .count();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn clocks_change() {
+        let mut tt = TimeTable::new();
+        tt.timezone = "Europe/Paris".to_owned();
+        add_stops(&mut tt, &[("eglise", "Église"), ("gare", "Gare")]);
+        add_service(&mut tt, "su1", WeekdayFlags::SUNDAY);
+        for (trip_id, hour) in [("late", 0), ("night", 1), ("morning", 8)] {
+            tt.journeys.push(trip(
+                trip_id,
+                "su1",
+                &[(hour, 30, "eglise"), (hour, 50, "gare")],
+            ));
+        }
+        // Clocks go forward at 02:00, so the service day starts at 23:00 on Saturday and times
+        // before the change are an hour ahead of the wall clock.
+        let (saturday, sunday) = (date(2024, 3, 30), date(2024, 3, 31));
+        let departures = |day| -> Vec<_> {
+            tt.get_day_stoptimes_and_destination_for_stop(
+                &day,
+                "Église",
+                None,
+                DepartureFilter::default(),
+            )
+            .map(|departure| (departure.time, departure.departure.time()))
+            .collect()
+        };
+        let time = |hour| NaiveTime::from_hms_opt(hour, 30, 0).unwrap();
+        assert_eq!(departures(sunday), [(time(0), time(0)), (time(8), time(8))]);
+        assert_eq!(departures(saturday), [(time(23), time(23))]);
+        let board = tt
+            .get_day_stoptimes_from_stop(&saturday, "Église", None)
+            .next()
+            .unwrap();
+        assert_eq!(board.departure_date(&tt.tz()), saturday);
+        assert_eq!(board.departure_time_of_day(&tt.tz()), time(23));
+    }
+
+    #[test]
+    fn services_active_on() {
+        let mut tt = sample_tt();
+        let workday = NaiveDate::from_yo_opt(2024, 8).unwrap();
+        let active = tt.get_services_active_on(workday);
+        assert_eq!(active.iter().collect::<Vec<_>>(), ["wd1"]);
+        tt.exceptions.insert(
+            "wd1".to_owned(),
+            ServiceException {
+                date: workday,
+                exception_type: Exception::Deleted,
+            },
+        );
+        // Cached until the timetable says it changed.
+        assert_eq!(tt.get_services_active_on(workday).len(), 1);
+        tt.invalidate_index();
+        assert!(tt.get_services_active_on(workday).is_empty());
+    }
+
+    #[test]
+    fn index_rebuilt_after_sort() {
+        let mut tt = sample_tt();
+        let day = NaiveDate::from_yo_opt(2024, 8).unwrap();
+        assert_eq!(
+            tt.get_day_stoptimes_from_stop(&day, "Église", None).count(),
+            2
+        );
+        let stops = [(6, 0, "eglise"), (6, 10, "gare")]
+            .into_iter()
+            .map(|(hour, minute, stop_id)| stop_time(hour, minute, stop_id))
+            .collect();
+        tt.journeys.push(journey("r1", "wd1", stops));
+        tt.sort_journeys_and_stops();
+        let departures: Vec<_> = tt
+            .get_day_stoptimes_from_a_to_b(&day, "Église", "Gare", None)
+            .map(|(from, _)| from.departure_time_of_day(&tt.tz()))
+            .collect();
+        assert_eq!(
+            departures,
+            [6, 14, 15].map(|hour| NaiveTime::from_hms_opt(hour, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn frequency_departures() {
+        let mut tt = sample_tt();
+        let template = journey(
+            "r1",
+            "wd1",
+            vec![stop_time(0, 0, "eglise"), stop_time(0, 10, "gare")],
+        );
+        let frequency = Frequency {
+            start_time: ServiceTime::from_hms(18, 0, 0),
+            end_time: ServiceTime::from_hms(19, 0, 0),
+            headway_secs: 20 * 60,
+            exact_times: false,
+        };
+        tt.journeys
+            .extend(template.expand_frequency(frequency).collect::<Vec<_>>());
+        tt.sort_journeys_and_stops();
+        let day = NaiveDate::from_yo_opt(2024, 8).unwrap();
+        let departures: Vec<_> = tt
+            .get_day_stoptimes_from_stop(&day, "Église", None)
+            .filter(|departure| !departure.journey.has_exact_times())
+            .map(|departure| departure.departure_time_of_day(&tt.tz()).to_string())
+            .collect();
+        assert_eq!(departures, ["18:00:00", "18:20:00", "18:40:00"]);
+    }
+
+    #[test]
+    fn next_departures_across_days() {
+        let mut tt = sample_tt();
+        add_night_journey(&mut tt);
+        tt.sort_journeys_and_stops();
+        let tz = chrono_tz::Europe::Paris;
+        // Friday evening, the night journey then weekend service.
+        let after = tz.with_ymd_and_hms(2024, 1, 12, 23, 55, 0).unwrap();
+        let departures: Vec<_> = tt
+            .get_next_departures_from_stop(&after, "Marché", None, DepartureFilter::default())
+            .take(4)
+            .map(|departure| departure.departure.to_rfc3339())
+            .collect();
+        assert_eq!(
+            departures,
+            [
+                "2024-01-13T00:05:00+01:00",
+                "2024-01-13T15:06:00+01:00",
+                "2024-01-13T16:06:00+01:00",
+                "2024-01-14T15:06:00+01:00",
+            ]
+        );
+        // Instants in another timezone are looked up in the feed's.
+        let after = after.with_timezone(&Utc);
+        let first = tt
+            .get_next_departures_from_stop(&after, "Marché", None, DepartureFilter::default())
+            .next()
+            .unwrap();
+        assert_eq!(first.time, NaiveTime::from_hms_opt(0, 5, 0).unwrap());
+        // Nothing within the horizon once the service patterns end.
+        let after = tz.with_ymd_and_hms(2024, 3, 16, 1, 0, 0).unwrap();
+        let count = tt
+            .get_next_departures_from_stop(&after, "Marché", None, DepartureFilter::default())
+            .count();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn route_filter() {
+        let mut tt = sample_tt();
+        tt.routes.insert(
+            "r2".to_owned(),
+            Route {
+                id: "r2".to_owned(),
+                short_name: None,
+                long_name: Some("Express".to_owned()),
+                color: None,
+                text_color: None,
+                agency_id: None,
+                agency_name: None,
+            },
+        );
+        let stops = [(17, 0, "eglise"), (17, 10, "gare")]
+            .into_iter()
+            .map(|(hour, minute, stop_id)| stop_time(hour, minute, stop_id))
+            .collect();
+        tt.journeys.push(journey("r2", "wd1", stops));
+        let day = NaiveDate::from_yo_opt(2024, 8).unwrap();
+        let count = tt.get_day_stoptimes_from_stop(&day, "Église", None).count();
+        assert_eq!(count, 3);
+        let count = tt
+            .get_day_stoptimes_from_stop(&day, "Église", Some("r1"))
+            .count();
+        assert_eq!(count, 2);
+        let stoptimes: Vec<_> = tt
+            .get_day_stoptimes_and_destination_for_stop(
+                &day,
+                "Église",
+                Some("r2"),
+                DepartureFilter::default(),
+            )
+            .collect();
+        assert_eq!(stoptimes.len(), 1);
+        assert_eq!(stoptimes[0].route_id, "r2");
+        assert_eq!(stoptimes[0].route_name.as_deref(), Some("Express"));
+        let stop_names = tt.get_stops_served_on_day(&day, Some("r2"));
+        assert_eq!(stop_names, ["Église", "Gare"].into());
+        let stoptimes: Vec<_> = tt
+            .get_day_stoptimes_and_destination_for_stop(
+                &day,
+                "Église",
+                Some("r1"),
+                DepartureFilter::default(),
+            )
+            .collect();
+        assert_eq!(stoptimes[0].route_name.as_deref(), Some("2245"));
+    }
+
+    #[test]
+    fn departure_filter() {
+        let mut tt = sample_tt();
+        for journey in &mut tt.journeys {
+            journey.direction_id = Some(Direction::Outbound);
+        }
+        let mut back = journey(
+            "r1",
+            "wd1",
+            vec![
+                stop_time(16, 0, "gare"),
+                stop_time(16, 9, "marche"),
+                stop_time(16, 15, "eglise"),
+            ],
+        );
+        back.direction_id = Some(Direction::Inbound);
+        tt.journeys.push(back);
+        let mut short_turn = journey(
+            "r1",
+            "wd1",
+            vec![stop_time(17, 0, "eglise"), stop_time(17, 6, "marche")],
+        );
+        short_turn.direction_id = Some(Direction::Outbound);
+        tt.journeys.push(short_turn);
+        let day = NaiveDate::from_yo_opt(2024, 8).unwrap();
+        let times = |stop_name, filter| -> Vec<_> {
+            tt.get_day_stoptimes_and_destination_for_stop(&day, stop_name, None, filter)
+                .map(|departure| departure.time.hour())
+                .collect()
+        };
+        assert_eq!(times("Marché", DepartureFilter::default()), [14, 15, 16]);
+        let outbound = DepartureFilter {
+            direction: Some(Direction::Outbound),
+            ..Default::default()
+        };
+        assert_eq!(times("Marché", outbound), [14, 15]);
+        let to_eglise = DepartureFilter {
+            terminus: Some("église"),
+            ..Default::default()
+        };
+        assert_eq!(times("Marché", to_eglise), [16]);
+        let to_gare = DepartureFilter {
+            terminus: Some("Gare"),
+            ..Default::default()
+        };
+        assert_eq!(times("Église", to_gare), [14, 15]);
+        // The short turn doesn't end at the market but goes past it.
+        let via_marche = DepartureFilter {
+            calls_at: Some(StopSelector::Name("Marché")),
+            ..Default::default()
+        };
+        assert_eq!(times("Église", via_marche), [14, 15, 17]);
+        assert_eq!(times("Gare", via_marche), [16]);
+        let potato = StopId::from("potato");
+        let via_potato = DepartureFilter {
+            calls_at: Some(StopSelector::Id(&potato)),
+            ..outbound
+        };
+        assert_eq!(times("Marché", via_potato), [14, 15]);
+    }
+
+    #[test]
+    fn quays_sharing_a_name() {
+        let mut tt = sample_tt();
+        let area = StopId::from("marche_area");
+        tt.stops.get_mut("marche").unwrap().parent_station = Some(area.clone());
+        let opposite_quay = stop("marche_2", "Marché", Some("marche_area"));
+        tt.stops.insert(opposite_quay.id.clone(), opposite_quay);
+        tt.journeys.push(journey(
+            "r1",
+            "wd1",
+            vec![stop_time(18, 0, "gare"), stop_time(18, 9, "marche_2")],
+        ));
+        assert_eq!(tt.get_stops_named("Marché").count(), 2);
+        assert_eq!(tt.get_stop_area_quays(&area).count(), 2);
+        let day = NaiveDate::from_yo_opt(2024, 8).unwrap();
+        let stop_ids: HashSet<_> = tt
+            .get_day_stoptimes_from_stop(&day, "Marché", None)
+            .map(|stop| stop.stop_time.stop_id.as_str())
+            .collect();
+        assert_eq!(stop_ids, ["marche", "marche_2"].into());
+
+        let quay = StopId::from("marche_2");
+        let stop_ids = |stops: StopSelector| -> HashSet<String> {
+            tt.get_day_stoptimes_from_stop(&day, stops, None)
+                .map(|stop| stop.stop_time.stop_id.to_string())
+                .collect()
+        };
+        assert_eq!(
+            stop_ids(StopSelector::Id(&quay)),
+            ["marche_2".to_owned()].into()
+        );
+        assert_eq!(stop_ids(StopSelector::Area(&area)).len(), 2);
+        let quays: Vec<_> = tt
+            .get_quays("Marché")
+            .into_iter()
+            .map(|stop| stop.id.as_str())
+            .collect();
+        assert_eq!(quays, ["marche", "marche_2"]);
+    }
+
+    #[test]
+    fn dwell_and_boarding_rules() {
+        let mut tt = sample_tt();
+        let mut stops = vec![
+            stop_time(17, 0, "eglise"),
+            stop_time(17, 6, "marche"),
+            stop_time(17, 10, "potato"),
+            stop_time(17, 15, "gare"),
+        ];
+        // Timing point: the bus waits two minutes at Marché.
+        stops[1].departure_time = ServiceTime::from_hms(17, 8, 0);
+        // Drop-off only at Potato Factory.
+        stops[2].pickup_type = PickupDropOff::NotAvailable;
+        // Pick-up only at Église.
+        stops[0].drop_off_type = PickupDropOff::NotAvailable;
+        tt.journeys.push(journey("r1", "wd1", stops));
+        let day = NaiveDate::from_yo_opt(2024, 8).unwrap();
+        let departures: Vec<_> = tt
+            .get_day_stoptimes_and_destination_for_stop(
+                &day,
+                "Marché",
+                None,
+                DepartureFilter::default(),
+            )
+            .map(|stop| stop.time)
+            .collect();
+        assert!(departures.contains(&NaiveTime::from_hms_opt(17, 8, 0).unwrap()));
+        assert!(!departures.contains(&NaiveTime::from_hms_opt(17, 6, 0).unwrap()));
+        // The terminus isn't a departure.
+        let count = tt
+            .get_day_stoptimes_and_destination_for_stop(
+                &day,
+                "Gare",
+                None,
+                DepartureFilter::default(),
+            )
+            .count();
+        assert_eq!(count, 0);
+        let count = tt
+            .get_day_stoptimes_from_stop(&day, "Potato Factory", None)
+            .count();
+        assert_eq!(count, 2);
+        let count = tt
+            .get_day_stoptimes_from_a_to_b(&day, "Potato Factory", "Gare", None)
+            .count();
+        assert_eq!(count, 2);
+        let count = tt
+            .get_day_stoptimes_from_a_to_b(&day, "Marché", "Église", None)
+            .count();
+        assert_eq!(count, 0);
+        // Stop order decides, the 15:00 journey reaches Potato Factory in the same minute.
+        let count = tt
+            .get_day_stoptimes_from_a_to_b(&day, "Marché", "Potato Factory", None)
+            .count();
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn departures_carry_variant() {
+        let mut tt = sample_tt();
+        let mut stops = tt.journeys[0].stops.clone();
+        stops.iter_mut().for_each(|stop| {
+            stop.arrival_time = ServiceTime::from_seconds(stop.arrival_time.seconds() + 7200);
+            stop.departure_time = stop.arrival_time;
+        });
+        tt.journeys.push(journey("r1", "wd1", stops));
+        let workday = NaiveDate::from_yo_opt(2024, 8).unwrap();
+        let departure = tt
+            .get_day_stoptimes_and_destination_for_stop(
+                &workday,
+                "Église",
+                None,
+                DepartureFilter::default(),
+            )
+            .next()
+            .unwrap();
+        assert_eq!(departure.variant, variant::MAIN_VARIANT_LABEL);
+        assert!(departure.main_variant);
+        let weekend = NaiveDate::from_yo_opt(2024, 6).unwrap();
+        let departure = tt
+            .get_day_stoptimes_and_destination_for_stop(
+                &weekend,
+                "Église",
+                None,
+                DepartureFilter::default(),
+            )
+            .next()
+            .unwrap();
+        assert_eq!(
+            departure.variant,
+            "via Terrain d'airsoft, skips Potato Factory"
+        );
+        assert!(!departure.main_variant);
+    }
+
+    #[test]
+    fn destination_from_headsign() {
+        let mut tt = sample_tt();
+        let mut short_turn = journey(
+            "r1",
+            "wd1",
+            vec![stop_time(18, 0, "eglise"), stop_time(18, 6, "marche")],
+        );
+        short_turn.trip_id = "short_turn".to_owned();
+        short_turn.trip_headsign = Some("Marché (short turn)".to_owned());
+        short_turn.direction_id = Some(Direction::Outbound);
+        tt.journeys.push(short_turn);
+        let day = NaiveDate::from_yo_opt(2024, 8).unwrap();
+        let departures: Vec<_> = tt
+            .get_day_stoptimes_and_destination_for_stop(
+                &day,
+                "Église",
+                None,
+                DepartureFilter::default(),
+            )
+            .collect();
+        assert_eq!(departures.len(), 3);
+        assert_eq!(departures[0].destination, "Gare");
+        assert_eq!(departures[0].direction_id, None);
+        assert_eq!(departures[2].trip_id, "short_turn");
+        assert_eq!(departures[2].destination, "Marché (short turn)");
+        assert_eq!(departures[2].direction_id, Some(Direction::Outbound));
+    }
 }
@@ -0,0 +1,369 @@
+//! Timetables extracted from several GTFS feeds, combined into one.
+//!
+//! Feeds are extracted separately, then merged. Route, trip, service and stop ids are prefixed
+//! with a namespace per feed since each publisher numbers them on its own. Stops the feeds share are
+//! linked by equivalence rules: calls at a linked stop become calls at the stop it is linked to,
+//! so that looking stops up by name and planning across feeds see a single stop.
+
+use crate::{StopId, TimeTable};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Rule telling which stops of different feeds are the same.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum StopEquivalence {
+    /// The second stop is the first one, both given with their namespaced id.
+    Ids(StopId, StopId),
+    /// Stops of the same kind and name less than this many meters apart are the same. Stops
+    /// without a position are never linked by this rule.
+    SameName { max_distance_meters: f64 },
+}
+
+#[derive(Debug)]
+pub enum Error {
+    NoTimeTable,
+    /// Feeds don't share a timezone, times of one would be read in the other's.
+    TimezoneMismatch {
+        expected: String,
+        found: String,
+    },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoTimeTable => write!(f, "no timetable to merge"),
+            Self::TimezoneMismatch { expected, found } => write!(
+                f,
+                "timetables to merge have different timezones, {expected} and {found}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl TimeTable {
+    /// Merges timetables, each given with the namespace its ids are prefixed with as
+    /// `namespace:id`. When a rule links two stops, the one from the feed coming first is kept.
+    pub fn merge<'n>(
+        timetables: impl IntoIterator<Item = (&'n str, TimeTable)>,
+        equivalences: &[StopEquivalence],
+    ) -> Result<TimeTable, Error> {
+        let mut timetables = timetables
+            .into_iter()
+            .map(|(namespace, timetable)| timetable.with_namespace(namespace));
+        let mut merged = timetables.next().ok_or(Error::NoTimeTable)?;
+        let mut links = HashMap::new();
+        for timetable in timetables {
+            if merged.timezone.is_empty() {
+                merged.timezone = timetable.timezone.clone();
+            } else if !timetable.timezone.is_empty() && timetable.timezone != merged.timezone {
+                return Err(Error::TimezoneMismatch {
+                    expected: merged.timezone,
+                    found: timetable.timezone,
+                });
+            }
+            // Only stops of feeds merged before can be linked to, so that stops of a single
+            // feed are never linked together.
+            for rule in equivalences {
+                if let StopEquivalence::SameName {
+                    max_distance_meters,
+                } = rule
+                {
+                    links.extend(same_name_links(&merged, &timetable, *max_distance_meters));
+                }
+            }
+            merged.absorb(timetable);
+        }
+        for rule in equivalences {
+            // Skipped when it would link stops in a loop.
+            if let StopEquivalence::Ids(kept, linked) = rule {
+                if resolve(&links, kept) != linked {
+                    links.insert(linked.clone(), kept.clone());
+                }
+            }
+        }
+        merged.link_stops(&links);
+        merged.sort_journeys_and_stops();
+        Ok(merged)
+    }
+
+    fn with_namespace(mut self, namespace: &str) -> TimeTable {
+        let prefix = |id: &str| format!("{namespace}:{id}");
+        let prefix_stop = |id: &StopId| StopId::from(prefix(id.as_str()));
+        self.routes = self
+            .routes
+            .into_values()
+            .map(|mut route| {
+                route.id = prefix(&route.id);
+                (route.id.clone(), route)
+            })
+            .collect();
+        for route_id in &mut self.extracted_route_ids {
+            *route_id = prefix(route_id);
+        }
+        self.stops = self
+            .stops
+            .into_values()
+            .map(|mut stop| {
+                stop.id = prefix_stop(&stop.id);
+                stop.parent_station = stop.parent_station.as_ref().map(prefix_stop);
+                (stop.id.clone(), stop)
+            })
+            .collect();
+        for journey in &mut self.journeys {
+            journey.trip_id = prefix(&journey.trip_id);
+            journey.route_id = prefix(&journey.route_id);
+            journey.service_id = prefix(&journey.service_id);
+            journey.block_id = journey.block_id.as_deref().map(prefix);
+            for stop in &mut journey.stops {
+                stop.stop_id = prefix_stop(&stop.stop_id);
+            }
+        }
+        for transfer in &mut self.transfers {
+            transfer.from_stop_id = prefix_stop(&transfer.from_stop_id);
+            transfer.to_stop_id = prefix_stop(&transfer.to_stop_id);
+        }
+        self.service_patterns = self
+            .service_patterns
+            .into_iter()
+            .map(|(service_id, pattern)| (prefix(&service_id), pattern))
+            .collect();
+        self.exceptions = self
+            .exceptions
+            .into_iter()
+            .flat_map(|(service_id, exceptions)| {
+                let service_id = prefix(&service_id);
+                exceptions
+                    .into_iter()
+                    .map(move |exception| (service_id.clone(), exception))
+            })
+            .collect();
+        self
+    }
+
+    /// Moves everything from the other timetable into this one, ids being already namespaced.
+    fn absorb(&mut self, other: TimeTable) {
+        self.routes.extend(other.routes);
+        self.stops.extend(other.stops);
+        self.transfers.extend(other.transfers);
+        self.journeys.extend(other.journeys);
+        for (service_id, exceptions) in other.exceptions {
+            self.exceptions.insert_many(service_id, exceptions);
+        }
+        self.service_patterns.extend(other.service_patterns);
+        self.extracted_on = self.extracted_on.min(other.extracted_on);
+        if !other.extracted_from.is_empty() {
+            self.extracted_from = [self.extracted_from.as_str(), &other.extracted_from]
+                .iter()
+                .filter(|source| !source.is_empty())
+                .copied()
+                .collect::<Vec<_>>()
+                .join(", ");
+        }
+        self.extracted_route_ids.extend(other.extracted_route_ids);
+        if self.holidays.is_empty() {
+            self.holidays = other.holidays;
+        }
+    }
+
+    /// Replaces linked stops with the ones they are linked to, following chains of links.
+    fn link_stops(&mut self, links: &HashMap<StopId, StopId>) {
+        let resolve = |stop_id: &StopId| resolve(links, stop_id).clone();
+        for journey in &mut self.journeys {
+            for stop in &mut journey.stops {
+                stop.stop_id = resolve(&stop.stop_id);
+            }
+        }
+        for transfer in &mut self.transfers {
+            transfer.from_stop_id = resolve(&transfer.from_stop_id);
+            transfer.to_stop_id = resolve(&transfer.to_stop_id);
+        }
+        for stop in self.stops.values_mut() {
+            stop.parent_station = stop.parent_station.as_ref().map(resolve);
+        }
+        self.stops.retain(|stop_id, _| resolve(stop_id) == *stop_id);
+    }
+}
+
+/// Stop the provided one ends up being after following links.
+fn resolve<'a>(links: &'a HashMap<StopId, StopId>, mut stop_id: &'a StopId) -> &'a StopId {
+    while let Some(next) = links.get(stop_id) {
+        stop_id = next;
+    }
+    stop_id
+}
+
+/// Links from stops of the other timetable to the closest stop of this one sharing their name.
+fn same_name_links(
+    tt: &TimeTable,
+    other: &TimeTable,
+    max_distance_meters: f64,
+) -> Vec<(StopId, StopId)> {
+    other
+        .stops
+        .values()
+        .filter_map(|stop| {
+            let (latitude, longitude) = (stop.latitude?, stop.longitude?);
+            let closest = tt
+                .stops
+                .values()
+                .filter(|candidate| {
+                    candidate.name == stop.name && candidate.location_type == stop.location_type
+                })
+                .filter_map(|candidate| {
+                    Some((candidate, candidate.distance_to(latitude, longitude)?))
+                })
+                .filter(|(_, distance)| *distance <= max_distance_meters)
+                .min_by(|(_, a), (_, b)| a.total_cmp(b))?;
+            Some((stop.id.clone(), closest.0.id.clone()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::planner::PlannerOptions;
+    use crate::test_util::*;
+    use crate::*;
+
+    /// One journey between two stops, on a workday service called `wd1` in every feed.
+    fn feed(stops: [(&str, &str, f64); 2], times: [(u32, u32); 2]) -> TimeTable {
+        let mut tt = TimeTable::new();
+        tt.timezone = "Europe/Paris".to_owned();
+        tt.routes.insert(
+            "r1".to_owned(),
+            Route {
+                id: "r1".to_owned(),
+                short_name: None,
+                long_name: Some(format!("{} - {}", stops[0].1, stops[1].1)),
+                color: None,
+                text_color: None,
+                agency_id: None,
+                agency_name: None,
+            },
+        );
+        tt.extracted_route_ids = vec!["r1".to_owned()];
+        for (id, name, longitude) in stops {
+            tt.stops.insert(
+                id.into(),
+                Stop {
+                    latitude: Some(48.9),
+                    longitude: Some(longitude),
+                    ..stop(id, name, None)
+                },
+            );
+        }
+        add_service(&mut tt, "wd1", WeekdayFlags::WORKDAYS);
+        let [(from, _, _), (to, _, _)] = stops;
+        let [(board_hour, board_minute), (alight_hour, alight_minute)] = times;
+        tt.journeys.push(trip(
+            &format!("{from}-{to}"),
+            "wd1",
+            &[
+                (board_hour, board_minute, from),
+                (alight_hour, alight_minute, to),
+            ],
+        ));
+        tt
+    }
+
+    /// Town buses to the station, and a neighbouring network's from the station to the port.
+    /// Both networks have a stop called `s1`, a route called `r1` and a trip called `s1-s2`.
+    fn feeds() -> [(&'static str, TimeTable); 2] {
+        [
+            (
+                "town",
+                feed(
+                    [("s1", "Église", 2.1), ("s2", "Gare", 2.12)],
+                    [(8, 0), (8, 20)],
+                ),
+            ),
+            (
+                "coast",
+                feed(
+                    [("s1", "Gare", 2.1201), ("s2", "Port", 2.2)],
+                    [(8, 30), (8, 50)],
+                ),
+            ),
+        ]
+    }
+
+    fn plan_to_port(tt: &TimeTable) -> Vec<usize> {
+        let after = chrono_tz::Europe::Paris
+            .with_ymd_and_hms(2024, 3, 4, 7, 50, 0)
+            .unwrap();
+        tt.plan("Église", "Port", &after, &PlannerOptions::default())
+            .iter()
+            .map(|itinerary| itinerary.transfers())
+            .collect()
+    }
+
+    #[test]
+    fn same_name() {
+        let rules = [StopEquivalence::SameName {
+            max_distance_meters: 50.0,
+        }];
+        let tt = TimeTable::merge(feeds(), &rules).unwrap();
+        assert_eq!(tt.stops.len(), 3);
+        assert_eq!(tt.get_stops_named("Gare").count(), 1);
+        assert!(tt.service_patterns.contains_key("town:wd1"));
+        assert!(tt.service_patterns.contains_key("coast:wd1"));
+        let coast_journey = tt
+            .journeys
+            .iter()
+            .find(|journey| journey.service_id == "coast:wd1")
+            .unwrap();
+        assert_eq!(coast_journey.stops[0].stop_id.as_str(), "town:s2");
+        assert_eq!(plan_to_port(&tt), [1]);
+
+        // Too far apart to be the same stop.
+        let rules = [StopEquivalence::SameName {
+            max_distance_meters: 1.0,
+        }];
+        let tt = TimeTable::merge(feeds(), &rules).unwrap();
+        assert_eq!(tt.get_stops_named("Gare").count(), 2);
+    }
+
+    #[test]
+    fn ids() {
+        let rules = [StopEquivalence::Ids("town:s2".into(), "coast:s1".into())];
+        let tt = TimeTable::merge(feeds(), &rules).unwrap();
+        assert_eq!(tt.stops.len(), 3);
+        assert_eq!(plan_to_port(&tt), [1]);
+        assert!(plan_to_port(&TimeTable::merge(feeds(), &[]).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn routes_and_trips() {
+        let tt = TimeTable::merge(feeds(), &[]).unwrap();
+        assert_eq!(tt.routes.len(), 2);
+        assert_eq!(
+            tt.routes["coast:r1"].long_name.as_deref(),
+            Some("Gare - Port")
+        );
+        assert_eq!(tt.extracted_route_ids, ["town:r1", "coast:r1"]);
+        let mut trips: Vec<_> = tt
+            .journeys
+            .iter()
+            .map(|journey| (journey.trip_id.as_str(), journey.route_id.as_str()))
+            .collect();
+        trips.sort();
+        assert_eq!(
+            trips,
+            [("coast:s1-s2", "coast:r1"), ("town:s1-s2", "town:r1")]
+        );
+    }
+
+    #[test]
+    fn timezones() {
+        let [town, (namespace, mut coast)] = feeds();
+        coast.timezone = "Europe/London".to_owned();
+        let Err(err) = TimeTable::merge([town, (namespace, coast)], &[]) else {
+            panic!("timetables in different timezones were merged");
+        };
+        assert!(matches!(err, Error::TimezoneMismatch { .. }));
+    }
+}
@@ -0,0 +1,488 @@
+//! Layouts of `TimeTable` written by past versions of the model, and their upgrade to the
+//! current one.
+//!
+//! Each past layout is frozen in its own module, copying the types it was written with so that
+//! later changes to the model don't change how old files read. Only value types such as ids,
+//! times and enums are shared with the current model, a layout must get its own copy before
+//! they change. Fields added with `#[serde(default)]` read fine from older files and don't need
+//! a new layout.
+
+use crate::storage::SCHEMA_VERSION;
+use crate::TimeTable;
+use serde::de::{self, DeserializeSeed, IntoDeserializer, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::cell::Cell;
+use std::collections::VecDeque;
+
+/// Layout of RON files written before the schema version was serialised: a single line, stop
+/// names on stop times and wall clock times. Stop times past midnight were dropped at extraction,
+/// so times never wrap around.
+mod v0 {
+    use crate::{ServiceException, ServicePattern};
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Deserialize)]
+    pub struct TimeTable {
+        pub timezone: String,
+        pub journeys: Vec<Journey>,
+        pub excpetions: multimap::MultiMap<String, ServiceException>,
+        pub service_patterns: HashMap<String, ServicePattern>,
+        pub extracted_on: chrono::DateTime<chrono::Utc>,
+        pub extracted_from: String,
+        pub extracted_line_id: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Journey {
+        pub service_id: String,
+        pub stops: Vec<StopTime>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct StopTime {
+        pub time: chrono::NaiveTime,
+        pub stop_name: String,
+        pub stop_id: String,
+    }
+}
+
+/// Layout with routes, stops and service times, before the schema version was serialised and
+/// with exceptions still misspelled.
+mod v1 {
+    use crate::{
+        Direction, LocationType, PickupDropOff, ServiceException, ServicePattern, ServiceTime,
+        StopId,
+    };
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Deserialize)]
+    pub struct Route {
+        pub id: String,
+        pub short_name: Option<String>,
+        pub long_name: Option<String>,
+        pub color: Option<String>,
+        pub text_color: Option<String>,
+        pub agency_id: Option<String>,
+        pub agency_name: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Stop {
+        pub id: StopId,
+        pub name: String,
+        pub latitude: Option<f64>,
+        pub longitude: Option<f64>,
+        pub parent_station: Option<StopId>,
+        pub location_type: LocationType,
+        pub platform_code: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Journey {
+        pub trip_id: String,
+        pub route_id: String,
+        pub service_id: String,
+        pub trip_headsign: Option<String>,
+        pub trip_short_name: Option<String>,
+        pub direction_id: Option<Direction>,
+        pub block_id: Option<String>,
+        pub stops: Vec<StopTime>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct StopTime {
+        pub arrival_time: ServiceTime,
+        pub departure_time: ServiceTime,
+        pub stop_id: StopId,
+        pub pickup_type: PickupDropOff,
+        pub drop_off_type: PickupDropOff,
+    }
+
+    #[derive(Deserialize)]
+    pub struct TimeTable {
+        pub timezone: String,
+        pub routes: HashMap<String, Route>,
+        pub stops: HashMap<StopId, Stop>,
+        pub journeys: Vec<Journey>,
+        pub excpetions: multimap::MultiMap<String, ServiceException>,
+        pub service_patterns: HashMap<String, ServicePattern>,
+        pub extracted_on: chrono::DateTime<chrono::Utc>,
+        pub extracted_from: String,
+        pub extracted_route_ids: Vec<String>,
+    }
+}
+
+impl From<v0::TimeTable> for v1::TimeTable {
+    fn from(value: v0::TimeTable) -> Self {
+        let route_id = value.extracted_line_id;
+        let mut stops = std::collections::HashMap::new();
+        let journeys = value
+            .journeys
+            .into_iter()
+            .enumerate()
+            .map(|(idx, journey)| {
+                for stop in journey.stops.iter() {
+                    stops
+                        .entry(crate::StopId::new(stop.stop_id.as_str()))
+                        .or_insert_with(|| v1::Stop {
+                            id: stop.stop_id.as_str().into(),
+                            name: stop.stop_name.clone(),
+                            latitude: None,
+                            longitude: None,
+                            parent_station: None,
+                            location_type: crate::LocationType::StopPoint,
+                            platform_code: None,
+                        });
+                }
+                v1::Journey {
+                    trip_id: format!("{}:{idx}", journey.service_id),
+                    route_id: route_id.clone(),
+                    service_id: journey.service_id,
+                    trip_headsign: None,
+                    trip_short_name: None,
+                    direction_id: None,
+                    block_id: None,
+                    stops: journey.stops.into_iter().map(Into::into).collect(),
+                }
+            })
+            .collect();
+        let routes = std::iter::once((
+            route_id.clone(),
+            v1::Route {
+                id: route_id.clone(),
+                short_name: None,
+                long_name: None,
+                color: None,
+                text_color: None,
+                agency_id: None,
+                agency_name: None,
+            },
+        ))
+        .collect();
+        Self {
+            timezone: value.timezone,
+            routes,
+            stops,
+            journeys,
+            excpetions: value.excpetions,
+            service_patterns: value.service_patterns,
+            extracted_on: value.extracted_on,
+            extracted_from: value.extracted_from,
+            extracted_route_ids: vec![route_id],
+        }
+    }
+}
+
+impl From<v0::StopTime> for v1::StopTime {
+    fn from(value: v0::StopTime) -> Self {
+        let time = value.time.into();
+        Self {
+            arrival_time: time,
+            departure_time: time,
+            stop_id: value.stop_id.into(),
+            pickup_type: crate::PickupDropOff::Regular,
+            drop_off_type: crate::PickupDropOff::Regular,
+        }
+    }
+}
+
+impl From<v1::Route> for crate::Route {
+    fn from(value: v1::Route) -> Self {
+        Self {
+            id: value.id,
+            short_name: value.short_name,
+            long_name: value.long_name,
+            color: value.color,
+            text_color: value.text_color,
+            agency_id: value.agency_id,
+            agency_name: value.agency_name,
+        }
+    }
+}
+
+impl From<v1::Stop> for crate::Stop {
+    fn from(value: v1::Stop) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            latitude: value.latitude,
+            longitude: value.longitude,
+            parent_station: value.parent_station,
+            location_type: value.location_type,
+            platform_code: value.platform_code,
+        }
+    }
+}
+
+impl From<v1::Journey> for crate::Journey {
+    fn from(value: v1::Journey) -> Self {
+        Self {
+            trip_id: value.trip_id,
+            route_id: value.route_id,
+            service_id: value.service_id,
+            trip_headsign: value.trip_headsign,
+            trip_short_name: value.trip_short_name,
+            direction_id: value.direction_id,
+            block_id: value.block_id,
+            stops: value.stops.into_iter().map(Into::into).collect(),
+            frequency: None,
+        }
+    }
+}
+
+impl From<v1::StopTime> for crate::StopTime {
+    fn from(value: v1::StopTime) -> Self {
+        Self {
+            arrival_time: value.arrival_time,
+            departure_time: value.departure_time,
+            stop_id: value.stop_id,
+            pickup_type: value.pickup_type,
+            drop_off_type: value.drop_off_type,
+        }
+    }
+}
+
+impl From<v1::TimeTable> for TimeTable {
+    fn from(value: v1::TimeTable) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            timezone: value.timezone,
+            routes: value
+                .routes
+                .into_iter()
+                .map(|(route_id, route)| (route_id, route.into()))
+                .collect(),
+            stops: value
+                .stops
+                .into_iter()
+                .map(|(stop_id, stop)| (stop_id, stop.into()))
+                .collect(),
+            journeys: value.journeys.into_iter().map(Into::into).collect(),
+            exceptions: value.excpetions,
+            service_patterns: value.service_patterns,
+            extracted_on: value.extracted_on,
+            extracted_from: value.extracted_from,
+            extracted_route_ids: value.extracted_route_ids,
+            ..Default::default()
+        }
+    }
+}
+
+/// Deserialise a timetable written with the provided schema version, upgrading it to the
+/// current layout.
+pub(crate) fn deserialize_version<'de, D: Deserializer<'de>>(
+    version: u16,
+    deserializer: D,
+) -> Result<TimeTable, D::Error> {
+    match version {
+        0 => v0::TimeTable::deserialize(deserializer)
+            .map(|tt| TimeTable::from(v1::TimeTable::from(tt))),
+        1 => v1::TimeTable::deserialize(deserializer).map(TimeTable::from),
+        _ => TimeTable::deserialize(deserializer),
+    }
+}
+
+/// Field name of a RON struct, which RON writes as a bare identifier.
+struct FieldName(String);
+
+impl<'de> Deserialize<'de> for FieldName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldNameVisitor;
+
+        impl<'de> Visitor<'de> for FieldNameVisitor {
+            type Value = FieldName;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a field name")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<FieldName, E> {
+                Ok(FieldName(value.to_owned()))
+            }
+        }
+
+        deserializer.deserialize_identifier(FieldNameVisitor)
+    }
+}
+
+/// Value of a field already read to tell the layout apart.
+enum ReadValue {
+    Version(u16),
+    Text(String),
+}
+
+/// Fields of a RON timetable, handing back the ones already read before the rest of the file.
+struct Replay<A> {
+    read: VecDeque<(String, Option<ReadValue>)>,
+    value: Option<ReadValue>,
+    map: A,
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for Replay<A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, A::Error> {
+        match self.read.pop_front() {
+            Some((key, value)) => {
+                self.value = value;
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => self.map.next_key_seed(seed),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, A::Error> {
+        match self.value.take() {
+            Some(ReadValue::Version(version)) => seed.deserialize(version.into_deserializer()),
+            Some(ReadValue::Text(text)) => seed.deserialize(text.into_deserializer()),
+            None => self.map.next_value_seed(seed),
+        }
+    }
+}
+
+/// Reads a RON timetable in a single pass, telling its layout from its first fields: current
+/// files start with `schema_version`, files from before versioning with the timezone followed
+/// by `journeys` for the first layout and `routes` for the second.
+struct RonTimeTable<'a> {
+    /// Set to the version of a file written by a newer model, which fails to read.
+    newer_version: &'a Cell<Option<u16>>,
+}
+
+impl<'de> Visitor<'de> for RonTimeTable<'_> {
+    type Value = TimeTable;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a timetable")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<TimeTable, A::Error> {
+        let Some(FieldName(first)) = map.next_key()? else {
+            return Err(de::Error::missing_field("timezone"));
+        };
+        let (version, read) = if first == "schema_version" {
+            let version = map.next_value()?;
+            if version > SCHEMA_VERSION {
+                self.newer_version.set(Some(version));
+                return Err(de::Error::custom(format!(
+                    "schema version {version} is not supported"
+                )));
+            }
+            (version, vec![(first, Some(ReadValue::Version(version)))])
+        } else {
+            let first_value = ReadValue::Text(map.next_value()?);
+            let second = map.next_key::<FieldName>()?;
+            let version = match &second {
+                Some(FieldName(key)) if key == "journeys" => 0,
+                _ => 1,
+            };
+            let read = std::iter::once((first, Some(first_value)))
+                .chain(second.map(|FieldName(key)| (key, None)))
+                .collect();
+            (version, read)
+        };
+        let replay = Replay {
+            read: read.into(),
+            value: None,
+            map,
+        };
+        deserialize_version(version, de::value::MapAccessDeserializer::new(replay))
+    }
+}
+
+/// Deserialise a RON timetable whichever layout it was written with, upgrading it to the
+/// current one. `newer_version` is set when the file comes from a newer model.
+pub(crate) fn deserialize_ron(
+    deserializer: &mut ron::Deserializer,
+    newer_version: &Cell<Option<u16>>,
+) -> Result<TimeTable, ron::Error> {
+    deserializer.deserialize_struct(
+        "TimeTable",
+        &["schema_version", "timezone"],
+        RonTimeTable { newer_version },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::storage::read_timetable;
+    use crate::*;
+
+    #[test]
+    fn v0_ron() {
+        let tt = read_timetable(include_bytes!("../fixtures/timetable_v0.ron").as_slice()).unwrap();
+        assert_eq!(tt.schema_version, storage::SCHEMA_VERSION);
+        assert_eq!(tt.extracted_route_ids, ["IDFM:C02298"]);
+        assert!(tt.routes.contains_key("IDFM:C02298"));
+        assert_eq!(tt.stops.len(), 3);
+        assert_eq!(tt.get_stop_name(&"IDFM:2".into()), "Marché");
+        assert_eq!(tt.exceptions.get_vec("wd1").unwrap().len(), 1);
+        assert!(tt
+            .journeys
+            .iter()
+            .all(|journey| journey.route_id == "IDFM:C02298"));
+        // The stop past midnight was dropped when the file was written.
+        let night = &tt.journeys[1];
+        assert_eq!(night.stops.len(), 2);
+        assert_eq!(
+            night.stops[1].arrival_time,
+            ServiceTime::from_hms(23, 58, 0)
+        );
+
+        let day = NaiveDate::from_ymd_opt(2024, 9, 3).unwrap();
+        let departures: Vec<_> = tt
+            .get_day_stoptimes_from_a_to_b(&day, "Église", "Marché", None)
+            .map(|(from, _)| from.departure_time_of_day(&tt.tz()))
+            .collect();
+        assert_eq!(
+            departures,
+            [
+                NaiveTime::from_hms_opt(7, 2, 0).unwrap(),
+                NaiveTime::from_hms_opt(23, 50, 0).unwrap()
+            ]
+        );
+    }
+
+    fn check_v1(tt: &TimeTable) {
+        assert_eq!(tt.schema_version, storage::SCHEMA_VERSION);
+        assert_eq!(tt.routes["IDFM:C02298"].display_name(), "2245");
+        assert_eq!(tt.stops.len(), 3);
+        assert_eq!(tt.journeys.len(), 2);
+        assert_eq!(tt.journeys[0].trip_headsign.as_deref(), Some("Gare"));
+        let holiday = NaiveDate::from_ymd_opt(2025, 5, 1).unwrap();
+        assert_eq!(tt.get_journeys_for_service_date(&holiday, None).count(), 0);
+    }
+
+    #[test]
+    fn v1_ron() {
+        let tt = read_timetable(include_bytes!("../fixtures/timetable_v1.ron").as_slice()).unwrap();
+        check_v1(&tt);
+    }
+
+    #[test]
+    fn v1_binary() {
+        let tt =
+            read_timetable(include_bytes!("../fixtures/timetable_v1.mstt").as_slice()).unwrap();
+        check_v1(&tt);
+    }
+
+    #[test]
+    fn current_ron_is_versioned() {
+        let mut bytes = vec![];
+        storage::write_timetable(&TimeTable::new(), &mut bytes, storage::Format::Ron).unwrap();
+        assert!(bytes.starts_with(b"(schema_version:"));
+        let newer = String::from_utf8(bytes).unwrap().replacen(
+            &format!("schema_version:{}", storage::SCHEMA_VERSION),
+            &format!("schema_version:{}", storage::SCHEMA_VERSION + 1),
+            1,
+        );
+        assert!(matches!(
+            read_timetable(newer.as_bytes()),
+            Err(storage::Error::UnsupportedVersion { found, .. }) if found == storage::SCHEMA_VERSION + 1
+        ));
+    }
+}
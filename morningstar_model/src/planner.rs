@@ -0,0 +1,477 @@
+//! Itineraries across routes, changing journeys along the way.
+//!
+//! Connections, a journey going from one stop to the next, are scanned in departure order as in
+//! the Connection Scan Algorithm. The scan runs in rounds: round `n` finds the earliest arrival
+//! at each stop on exactly `n` journeys, boarding from where round `n - 1` arrived after the
+//! transfer time. Keeping a round's itinerary only when it arrives sooner than the rounds before
+//! gives the Pareto set trading arrival time against number of transfers.
+
+use crate::{DatedJourney, DatedStopTime, StopId, StopSelector, TimeTable};
+use chrono::prelude::*;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct PlannerOptions {
+    /// Transfers allowed in a single itinerary.
+    pub max_transfers: usize,
+    /// Seconds needed to change journeys at a stop when `transfers.txt` doesn't say, also
+    /// allowed to walk between quays of the same stop area.
+    pub default_transfer_secs: u32,
+    /// Service days looked at after the one the search starts on.
+    pub horizon_days: u64,
+}
+
+impl Default for PlannerOptions {
+    fn default() -> Self {
+        Self {
+            max_transfers: 3,
+            default_transfer_secs: 180,
+            horizon_days: 1,
+        }
+    }
+}
+
+/// Ride on a single journey, from boarding to alighting.
+#[derive(Debug, Clone)]
+pub struct Leg<'a> {
+    pub board: DatedStopTime<'a>,
+    pub alight: DatedStopTime<'a>,
+    /// Departure from the boarding stop, in the feed's timezone.
+    pub departure: DateTime<FixedOffset>,
+    /// Arrival at the alighting stop, in the feed's timezone.
+    pub arrival: DateTime<FixedOffset>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Itinerary<'a> {
+    pub legs: Vec<Leg<'a>>,
+}
+
+impl Itinerary<'_> {
+    pub fn departure(&self) -> DateTime<FixedOffset> {
+        self.legs
+            .first()
+            .expect("itineraries to have legs")
+            .departure
+    }
+
+    pub fn arrival(&self) -> DateTime<FixedOffset> {
+        self.legs.last().expect("itineraries to have legs").arrival
+    }
+
+    pub fn transfers(&self) -> usize {
+        self.legs.len().saturating_sub(1)
+    }
+}
+
+/// A dated journey going from the stop at `position` to the next one.
+struct Connection {
+    journey: usize,
+    position: usize,
+    from: usize,
+    to: usize,
+    departure: i64,
+    arrival: i64,
+}
+
+/// Best arrival at a stop in a round, with the ride that got there.
+#[derive(Clone, Copy)]
+struct Label {
+    arrival: i64,
+    journey: usize,
+    board_position: usize,
+    alight_position: usize,
+}
+
+/// Earliest time a journey can be boarded at a stop, with the stop arrived at before walking or
+/// waiting there.
+#[derive(Clone, Copy)]
+struct Ready {
+    time: i64,
+    arrived_at: Option<usize>,
+}
+
+impl TimeTable {
+    /// Itineraries from a stop to another leaving at or after provided instant, stops being
+    /// given by exact name, id or stop area. Each itinerary arrives sooner than those with fewer
+    /// transfers, which come first.
+    pub fn plan<'s, Tz: TimeZone>(
+        &self,
+        from: impl Into<StopSelector<'s>>,
+        to: impl Into<StopSelector<'s>>,
+        after: &DateTime<Tz>,
+        options: &PlannerOptions,
+    ) -> Vec<Itinerary<'_>> {
+        Planner::new(self, after, options).run(from.into(), to.into(), after.timestamp(), options)
+    }
+}
+
+struct Planner<'a> {
+    timetable: &'a TimeTable,
+    tz: chrono_tz::Tz,
+    journeys: Vec<DatedJourney<'a>>,
+    stops: Vec<&'a StopId>,
+    stop_indices: HashMap<&'a StopId, usize>,
+    connections: Vec<Connection>,
+    /// Stops reachable after arriving at a stop, with the seconds it takes. A stop is its own
+    /// neighbour, as changing journeys takes time too.
+    neighbours: Vec<Vec<(usize, u32)>>,
+}
+
+impl<'a> Planner<'a> {
+    fn new<Tz: TimeZone>(
+        timetable: &'a TimeTable,
+        after: &DateTime<Tz>,
+        options: &PlannerOptions,
+    ) -> Self {
+        let tz = timetable.tz();
+        let first_day = after.with_timezone(&tz).date_naive();
+        let mut planner = Self {
+            timetable,
+            tz,
+            journeys: vec![],
+            stops: vec![],
+            stop_indices: HashMap::new(),
+            connections: vec![],
+            neighbours: vec![],
+        };
+        let after = after.timestamp();
+        // Journeys of the previous service day can still be running after midnight.
+        let service_dates = first_day
+            .pred_opt()
+            .unwrap_or(first_day)
+            .iter_days()
+            .take(options.horizon_days as usize + 2);
+        for service_date in service_dates {
+            let journeys =
+                timetable.journeys_on_service_date(0..timetable.journeys.len(), service_date, None);
+            for (_, journey) in journeys {
+                planner.add_connections(journey, after);
+            }
+        }
+        planner
+            .connections
+            .sort_by_key(|conn| (conn.departure, conn.arrival, conn.journey, conn.position));
+        planner.neighbours = planner.make_neighbours(options.default_transfer_secs);
+        planner
+    }
+
+    fn stop_index(&mut self, stop_id: &'a StopId) -> usize {
+        *self.stop_indices.entry(stop_id).or_insert_with(|| {
+            self.stops.push(stop_id);
+            self.stops.len() - 1
+        })
+    }
+
+    fn add_connections(&mut self, journey: DatedJourney<'a>, after: i64) {
+        let journey_idx = self.journeys.len();
+        let stops: Vec<_> = journey.all_stops().collect();
+        let mut added = false;
+        for (position, pair) in stops.windows(2).enumerate() {
+            let departure = pair[0].departure_datetime(&self.tz).timestamp();
+            if departure < after {
+                continue;
+            }
+            let connection = Connection {
+                journey: journey_idx,
+                position,
+                from: self.stop_index(&pair[0].stop_time.stop_id),
+                to: self.stop_index(&pair[1].stop_time.stop_id),
+                departure,
+                arrival: pair[1].arrival_datetime(&self.tz).timestamp(),
+            };
+            self.connections.push(connection);
+            added = true;
+        }
+        if added {
+            self.journeys.push(journey);
+        }
+    }
+
+    fn make_neighbours(&self, default_secs: u32) -> Vec<Vec<(usize, u32)>> {
+        let mut durations: HashMap<(usize, usize), Option<u32>> = HashMap::new();
+        for idx in 0..self.stops.len() {
+            durations.insert((idx, idx), Some(default_secs));
+        }
+        // Quays of a stop area are a short walk apart.
+        let mut quays_by_area: HashMap<&StopId, Vec<usize>> = HashMap::new();
+        for (idx, stop_id) in self.stops.iter().enumerate() {
+            if let Some(area) = self
+                .timetable
+                .get_stop(stop_id)
+                .and_then(|stop| stop.parent_station.as_ref())
+            {
+                quays_by_area.entry(area).or_default().push(idx);
+            }
+        }
+        for quays in quays_by_area.values() {
+            for from in quays {
+                for to in quays {
+                    durations.insert((*from, *to), Some(default_secs));
+                }
+            }
+        }
+        // Published rules come last so they take precedence.
+        for transfer in &self.timetable.transfers {
+            let (Some(from), Some(to)) = (
+                self.stop_indices.get(&transfer.from_stop_id),
+                self.stop_indices.get(&transfer.to_stop_id),
+            ) else {
+                continue;
+            };
+            durations.insert((*from, *to), transfer.duration(default_secs));
+        }
+        let mut neighbours = vec![vec![]; self.stops.len()];
+        for ((from, to), duration) in durations {
+            if let Some(duration) = duration {
+                neighbours[from].push((to, duration));
+            }
+        }
+        neighbours
+    }
+
+    fn stops_selected(&self, stops: StopSelector) -> Vec<usize> {
+        self.timetable
+            .get_stop_ids(stops)
+            .iter()
+            .filter_map(|stop_id| self.stop_indices.get(stop_id).copied())
+            .collect()
+    }
+
+    fn run(
+        &self,
+        from: StopSelector,
+        to: StopSelector,
+        after: i64,
+        options: &PlannerOptions,
+    ) -> Vec<Itinerary<'a>> {
+        let targets = self.stops_selected(to);
+        let unreached = Ready {
+            time: i64::MAX,
+            arrived_at: None,
+        };
+        let mut ready = vec![unreached; self.stops.len()];
+        for source in self.stops_selected(from) {
+            ready[source].time = after;
+        }
+        // Labels and boarding times of every round, kept to trace itineraries back.
+        let mut rounds: Vec<(Vec<Option<Label>>, Vec<Ready>)> = vec![];
+        let mut itineraries = vec![];
+        let mut best_arrival = i64::MAX;
+        for _ in 0..=options.max_transfers {
+            let labels = self.scan(&ready);
+            let arrival = targets
+                .iter()
+                .filter_map(|target| labels[*target].map(|label| (label.arrival, *target)))
+                .min();
+            let next_ready = self.transfer(&labels);
+            rounds.push((labels, ready));
+            if let Some((arrival, target)) = arrival {
+                if arrival < best_arrival {
+                    best_arrival = arrival;
+                    itineraries.push(self.trace(&rounds, target));
+                }
+            }
+            if next_ready.iter().all(|ready| ready.time == i64::MAX) {
+                break;
+            }
+            ready = next_ready;
+        }
+        itineraries
+    }
+
+    /// Earliest arrivals riding one more journey, boarded where `ready` allows.
+    fn scan(&self, ready: &[Ready]) -> Vec<Option<Label>> {
+        let mut labels: Vec<Option<Label>> = vec![None; self.stops.len()];
+        let mut boarded_at: Vec<Option<usize>> = vec![None; self.journeys.len()];
+        for conn in &self.connections {
+            let journey = &self.journeys[conn.journey];
+            if boarded_at[conn.journey].is_none()
+                && ready[conn.from].time <= conn.departure
+                && journey.stops[conn.position].can_board()
+            {
+                boarded_at[conn.journey] = Some(conn.position);
+            }
+            let Some(board_position) = boarded_at[conn.journey] else {
+                continue;
+            };
+            let alight_position = conn.position + 1;
+            if journey.stops[alight_position].can_alight()
+                && labels[conn.to].is_none_or(|label| conn.arrival < label.arrival)
+            {
+                labels[conn.to] = Some(Label {
+                    arrival: conn.arrival,
+                    journey: conn.journey,
+                    board_position,
+                    alight_position,
+                });
+            }
+        }
+        labels
+    }
+
+    /// When the next journey can be boarded at each stop after arriving with `labels`.
+    fn transfer(&self, labels: &[Option<Label>]) -> Vec<Ready> {
+        let mut ready = vec![
+            Ready {
+                time: i64::MAX,
+                arrived_at: None,
+            };
+            self.stops.len()
+        ];
+        for (stop, label) in labels.iter().enumerate() {
+            let Some(label) = label else {
+                continue;
+            };
+            for (neighbour, duration) in &self.neighbours[stop] {
+                let time = label.arrival + *duration as i64;
+                if time < ready[*neighbour].time {
+                    ready[*neighbour] = Ready {
+                        time,
+                        arrived_at: Some(stop),
+                    };
+                }
+            }
+        }
+        ready
+    }
+
+    /// Legs of the itinerary reaching `target` in the last round.
+    fn trace(&self, rounds: &[(Vec<Option<Label>>, Vec<Ready>)], target: usize) -> Itinerary<'a> {
+        let mut legs = vec![];
+        let mut stop = target;
+        for (labels, ready) in rounds.iter().rev() {
+            let label = labels[stop].expect("traced stops to be reached");
+            let journey = self.journeys[label.journey];
+            let board = journey.stop_at(label.board_position);
+            let alight = journey.stop_at(label.alight_position);
+            legs.push(Leg {
+                board,
+                alight,
+                departure: board.departure_datetime(&self.tz).fixed_offset(),
+                arrival: alight.arrival_datetime(&self.tz).fixed_offset(),
+            });
+            let board_stop = self.stop_indices[&board.stop_time.stop_id];
+            match ready[board_stop].arrived_at {
+                Some(arrived_at) => stop = arrived_at,
+                None => break,
+            }
+        }
+        legs.reverse();
+        Itinerary { legs }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+    use crate::*;
+
+    fn add_journey(tt: &mut TimeTable, trip_id: &str, route_id: &str, stops: &[(u32, u32, &str)]) {
+        for (_, _, stop_id) in stops {
+            tt.stops.insert(
+                (*stop_id).into(),
+                stop(stop_id, &stop_id.to_uppercase(), None),
+            );
+        }
+        tt.journeys.push(Journey {
+            route_id: route_id.to_owned(),
+            ..trip(trip_id, "wd1", stops)
+        });
+    }
+
+    /// A slow direct line from a to d, and a faster one to c with a connection to d.
+    fn sample_tt() -> TimeTable {
+        let mut tt = TimeTable::new();
+        add_service(&mut tt, "wd1", WeekdayFlags::WORKDAYS);
+        add_journey(
+            &mut tt,
+            "slow",
+            "r1",
+            &[(8, 0, "a"), (8, 30, "b"), (9, 30, "d")],
+        );
+        add_journey(&mut tt, "fast", "r2", &[(8, 5, "a"), (8, 20, "c")]);
+        add_journey(&mut tt, "tight", "r3", &[(8, 21, "c"), (8, 40, "d")]);
+        add_journey(&mut tt, "feeder", "r3", &[(8, 25, "c"), (8, 50, "d")]);
+        tt.sort_journeys_and_stops();
+        tt
+    }
+
+    fn trips(itinerary: &Itinerary) -> Vec<String> {
+        itinerary
+            .legs
+            .iter()
+            .map(|leg| leg.board.journey.trip_id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn pareto_itineraries() {
+        let tt = sample_tt();
+        let after = chrono_tz::Europe::Paris
+            .with_ymd_and_hms(2024, 3, 4, 7, 50, 0)
+            .unwrap();
+        let itineraries = tt.plan("A", "D", &after, &PlannerOptions::default());
+        assert_eq!(itineraries.len(), 2);
+        assert_eq!(trips(&itineraries[0]), ["slow"]);
+        assert_eq!(itineraries[0].transfers(), 0);
+        // The 08:21 leaves before the default transfer time has passed.
+        assert_eq!(trips(&itineraries[1]), ["fast", "feeder"]);
+        assert_eq!(
+            itineraries[1].arrival().to_rfc3339(),
+            "2024-03-04T08:50:00+01:00"
+        );
+        assert_eq!(itineraries[1].legs[1].board.stop_time.stop_id.as_str(), "c");
+        let (a, d) = (StopId::from("a"), StopId::from("d"));
+        let by_id = tt.plan(&a, &d, &after, &PlannerOptions::default());
+        assert_eq!(by_id.len(), 2);
+    }
+
+    #[test]
+    fn published_transfers() {
+        let mut tt = sample_tt();
+        tt.transfers.push(Transfer {
+            from_stop_id: "c".into(),
+            to_stop_id: "c".into(),
+            kind: TransferKind::Timed,
+            min_transfer_time: None,
+        });
+        let after = chrono_tz::Europe::Paris
+            .with_ymd_and_hms(2024, 3, 4, 7, 50, 0)
+            .unwrap();
+        let itineraries = tt.plan("A", "D", &after, &PlannerOptions::default());
+        assert_eq!(trips(&itineraries[1]), ["fast", "tight"]);
+
+        tt.transfers[0].kind = TransferKind::NotPossible;
+        let itineraries = tt.plan("A", "D", &after, &PlannerOptions::default());
+        assert_eq!(itineraries.len(), 1);
+        let options = PlannerOptions {
+            max_transfers: 0,
+            ..Default::default()
+        };
+        tt.transfers.clear();
+        assert_eq!(tt.plan("A", "D", &after, &options).len(), 1);
+    }
+
+    #[test]
+    fn next_day() {
+        let tt = sample_tt();
+        // Friday evening, the next bus is on monday and out of the default horizon.
+        let after = chrono_tz::Europe::Paris
+            .with_ymd_and_hms(2024, 3, 8, 20, 0, 0)
+            .unwrap();
+        assert!(tt
+            .plan("A", "D", &after, &PlannerOptions::default())
+            .is_empty());
+        let options = PlannerOptions {
+            horizon_days: 3,
+            ..Default::default()
+        };
+        let itineraries = tt.plan("A", "D", &after, &options);
+        assert_eq!(
+            itineraries[0].departure().to_rfc3339(),
+            "2024-03-11T08:00:00+01:00"
+        );
+    }
+}
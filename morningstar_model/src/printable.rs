@@ -0,0 +1,560 @@
+//! Printable timetables for a stop, in the classic grid layout: a row per hour listing the minutes
+//! of its departures, a column per kind of day. Footnote letters mark departures following another
+//! stop pattern than the main one, and departures that don't run every day of their column or for
+//! the whole timetable.
+//!
+//! Departures are those of a reference week, rendered as self-contained HTML or SVG. Departures
+//! after midnight go with the day their service runs on, at the end of its column.
+
+use crate::{
+    DepartureFilter, Direction, StopSelector, StopTimeWithDestination, TimeTable, WeekdayFlags,
+};
+use chrono::{Datelike, NaiveDate, Timelike};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+/// Hour at which the service day starts for display, departures before it come last.
+const FIRST_HOUR: u32 = 4;
+
+const COLUMNS: [(&str, WeekdayFlags); 3] = [
+    ("Monday to Friday", WeekdayFlags::WORKDAYS),
+    ("Saturday", WeekdayFlags::SATURDAY),
+    ("Sunday", WeekdayFlags::SUNDAY),
+];
+
+const WEEKDAY_NAMES: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+#[derive(Debug, Clone)]
+pub struct Grid {
+    pub stop_name: String,
+    pub route_name: Option<String>,
+    /// Destination most departures are headed to.
+    pub destination: String,
+    /// Monday of the week the departures are taken from.
+    pub week_of: NaiveDate,
+    /// Kinds of day with departures, among Monday to Friday, Saturday and Sunday.
+    pub columns: Vec<GridColumn>,
+    pub footnotes: Vec<Footnote>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GridColumn {
+    pub title: &'static str,
+    /// Hours with departures, in the order of the service day.
+    pub rows: Vec<GridRow>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GridRow {
+    pub hour: u32,
+    pub departures: Vec<GridDeparture>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GridDeparture {
+    pub minute: u32,
+    /// Whether the time is kept rather than estimated from a headway.
+    pub exact_time: bool,
+    pub footnote: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Footnote {
+    pub mark: String,
+    pub text: String,
+}
+
+/// What makes departures at the same time of a column the same line of the grid.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct DepartureKey {
+    /// Service day order of the hour, then minute.
+    sort_time: (u32, u32),
+    variant: Option<String>,
+    dates: Option<String>,
+}
+
+impl TimeTable {
+    /// Grid timetable of departures from a stop, over the week starting on the Monday of
+    /// `week_of`. The stop is given by exact name, id or stop area.
+    pub fn get_printable_grid<'a>(
+        &'a self,
+        stop: impl Into<StopSelector<'a>>,
+        route_id: Option<&'a str>,
+        direction: Option<Direction>,
+        week_of: NaiveDate,
+    ) -> Grid {
+        let stop = stop.into();
+        let monday = week_of - chrono::Days::new(week_of.weekday().num_days_from_monday() as u64);
+        let filter = DepartureFilter {
+            direction,
+            ..Default::default()
+        };
+        let service_ids: HashMap<_, _> = self
+            .journeys
+            .iter()
+            .map(|journey| (journey.trip_id.as_str(), journey.service_id.as_str()))
+            .collect();
+        let window = self.get_validity_window();
+        let mut date_restrictions = HashMap::new();
+        let mut destinations: HashMap<String, usize> = HashMap::new();
+        let mut route_name = None;
+        let mut columns = vec![];
+        let mut footnotes: Vec<Footnote> = vec![];
+        for (title, column_days) in COLUMNS {
+            let mut days_served = WeekdayFlags::NEVER;
+            let mut departures: BTreeMap<DepartureKey, (bool, WeekdayFlags)> = BTreeMap::new();
+            for day in monday.iter_days().take(7) {
+                let weekday = weekday_flag(day);
+                if !column_days.contains(weekday) {
+                    continue;
+                }
+                // Departures after midnight belong to the previous day's services, and those
+                // before clocks go forward to the next day's.
+                let calendar_days = [day - chrono::Days::new(1), day, day + chrono::Days::new(1)];
+                let day_departures = calendar_days
+                    .iter()
+                    .flat_map(|calendar_day| {
+                        self.get_day_stoptimes_and_destination_for_stop(
+                            calendar_day,
+                            stop,
+                            route_id,
+                            filter,
+                        )
+                    })
+                    .filter(|departure| departure.service_date == day);
+                for departure in day_departures {
+                    days_served |= weekday;
+                    *destinations
+                        .entry(departure.destination.clone())
+                        .or_default() += 1;
+                    route_name = route_name.or(departure.route_name.clone());
+                    let service_id = service_ids.get(departure.trip_id.as_str()).copied();
+                    let dates = service_id.and_then(|service_id| {
+                        date_restrictions
+                            .entry(service_id)
+                            .or_insert_with(|| self.date_restriction(service_id, window.as_ref()))
+                            .clone()
+                    });
+                    let key = departure_key(&departure, dates);
+                    let (exact_time, days) = departures.entry(key).or_insert((true, weekday));
+                    *exact_time &= departure.exact_time;
+                    *days |= weekday;
+                }
+            }
+            if departures.is_empty() {
+                continue;
+            }
+            let mut rows: Vec<GridRow> = vec![];
+            for (key, (exact_time, days)) in departures {
+                let hour = (key.sort_time.0 + FIRST_HOUR) % 24;
+                let notes: Vec<_> = [
+                    key.variant,
+                    (days != days_served).then(|| only_on(days)),
+                    key.dates,
+                ]
+                .into_iter()
+                .flatten()
+                .collect();
+                let footnote = (!notes.is_empty()).then(|| footnote_mark(&mut footnotes, notes));
+                let departure = GridDeparture {
+                    minute: key.sort_time.1,
+                    exact_time,
+                    footnote,
+                };
+                match rows.last_mut() {
+                    Some(row) if row.hour == hour => row.departures.push(departure),
+                    _ => rows.push(GridRow {
+                        hour,
+                        departures: vec![departure],
+                    }),
+                }
+            }
+            columns.push(GridColumn { title, rows });
+        }
+        let destination = destinations
+            .into_iter()
+            .max_by(|(a_name, a_count), (b_name, b_count)| {
+                a_count.cmp(b_count).then(b_name.cmp(a_name))
+            })
+            .map(|(destination, _)| destination)
+            .unwrap_or_default();
+        Grid {
+            stop_name: self.get_selected_name(stop).to_owned(),
+            route_name,
+            destination,
+            week_of: monday,
+            columns,
+            footnotes,
+        }
+    }
+
+    /// Dates the service is restricted to compared to the whole timetable, when it is. Services
+    /// running on some days of the week only start and end within a week of the timetable.
+    fn date_restriction(
+        &self,
+        service_id: &str,
+        window: Option<&std::ops::RangeInclusive<NaiveDate>>,
+    ) -> Option<String> {
+        let window = window?;
+        let dates = self.get_service_dates(service_id);
+        let (first, last) = (*dates.first()?, *dates.last()?);
+        let week = chrono::Days::new(7);
+        match (
+            first >= *window.start() + week,
+            last + week <= *window.end(),
+        ) {
+            (true, true) => Some(format!("from {first} until {last}")),
+            (true, false) => Some(format!("from {first}")),
+            (false, true) => Some(format!("until {last}")),
+            (false, false) => None,
+        }
+    }
+}
+
+fn departure_key(departure: &StopTimeWithDestination, dates: Option<String>) -> DepartureKey {
+    let (hour, minute) = (departure.time.hour(), departure.time.minute());
+    DepartureKey {
+        sort_time: ((hour + 24 - FIRST_HOUR) % 24, minute),
+        variant: Some(departure.variant.clone()).filter(|_| !departure.main_variant),
+        dates,
+    }
+}
+
+fn weekday_flag(day: NaiveDate) -> WeekdayFlags {
+    WeekdayFlags::from_bits_truncate(1 << day.weekday().num_days_from_monday())
+}
+
+fn only_on(days: WeekdayFlags) -> String {
+    let names: Vec<_> = WEEKDAY_NAMES
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| days.contains(WeekdayFlags::from_bits_truncate(1 << idx)))
+        .map(|(_, name)| *name)
+        .collect();
+    format!("only on {}", names.join(", "))
+}
+
+/// Mark of the footnote with provided text, added when it's the first departure needing it.
+/// Marks go from `a` to `z`, then `aa`, `ab`...
+fn footnote_mark(footnotes: &mut Vec<Footnote>, notes: Vec<String>) -> String {
+    let text = notes.join("; ");
+    if let Some(footnote) = footnotes.iter().find(|footnote| footnote.text == text) {
+        return footnote.mark.clone();
+    }
+    let idx = footnotes.len();
+    let letter = |idx: usize| char::from(b'a' + (idx % 26) as u8);
+    let mark = if idx < 26 {
+        letter(idx).to_string()
+    } else {
+        format!("{}{}", letter(idx / 26 - 1), letter(idx))
+    };
+    footnotes.push(Footnote {
+        mark: mark.clone(),
+        text,
+    });
+    mark
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl Grid {
+    fn title(&self) -> String {
+        match &self.route_name {
+            Some(route_name) => format!("{route_name} – {}", self.stop_name),
+            None => self.stop_name.clone(),
+        }
+    }
+
+    fn has_estimates(&self) -> bool {
+        self.columns
+            .iter()
+            .flat_map(|column| &column.rows)
+            .flat_map(|row| &row.departures)
+            .any(|departure| !departure.exact_time)
+    }
+
+    /// Standalone HTML page, laid out for printing on a single sheet.
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        let title = escape(&self.title());
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{title}</title>\n<style>\n\
+             body {{ font-family: sans-serif; margin: 1cm; }}\n\
+             h1 {{ margin: 0; font-size: 1.6em; }}\n\
+             .columns {{ display: flex; gap: 1em; align-items: flex-start; margin: 1em 0; }}\n\
+             table {{ border-collapse: collapse; }}\n\
+             th, td {{ border: 1px solid #000; padding: 0.15em 0.4em; text-align: left; }}\n\
+             tbody th {{ width: 2em; text-align: right; background: #eee; }}\n\
+             td span {{ display: inline-block; min-width: 1.8em; }}\n\
+             @media print {{ body {{ margin: 0; }} }}\n\
+             </style>\n</head>\n<body>\n<h1>{title}</h1>\n<p>Towards {} – week of {}</p>\n\
+             <div class=\"columns\">\n",
+            escape(&self.destination),
+            self.week_of
+        );
+        for column in &self.columns {
+            let _ = write!(
+                html,
+                "<table>\n<thead><tr><th colspan=\"2\">{}</th></tr></thead>\n<tbody>\n",
+                column.title
+            );
+            for row in &column.rows {
+                let _ = write!(html, "<tr><th>{:02}</th><td>", row.hour);
+                for departure in &row.departures {
+                    let _ = write!(
+                        html,
+                        "<span>{}{:02}",
+                        if departure.exact_time { "" } else { "~" },
+                        departure.minute
+                    );
+                    if let Some(mark) = &departure.footnote {
+                        let _ = write!(html, "<sup>{mark}</sup>");
+                    }
+                    html.push_str("</span>");
+                }
+                html.push_str("</td></tr>\n");
+            }
+            html.push_str("</tbody>\n</table>\n");
+        }
+        html.push_str("</div>\n");
+        if !self.footnotes.is_empty() || self.has_estimates() {
+            html.push_str("<p>\n");
+            for footnote in &self.footnotes {
+                let _ = writeln!(
+                    html,
+                    "<b>{}</b> {}<br>",
+                    footnote.mark,
+                    escape(&footnote.text)
+                );
+            }
+            if self.has_estimates() {
+                html.push_str("<b>~</b> estimated time, buses run at the stated frequency<br>\n");
+            }
+            html.push_str("</p>\n");
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    /// Standalone SVG image, columns side by side and footnotes below.
+    pub fn to_svg(&self) -> String {
+        const COLUMN_WIDTH: usize = 260;
+        const HOUR_WIDTH: usize = 30;
+        const MINUTE_WIDTH: usize = 30;
+        const LINE_HEIGHT: usize = 18;
+        const HEADER_HEIGHT: usize = 70;
+        // Minutes wrap onto another line when an hour has too many departures to fit.
+        let minutes_per_line = (COLUMN_WIDTH - HOUR_WIDTH - 10) / MINUTE_WIDTH;
+        let lines = |row: &GridRow| row.departures.len().div_ceil(minutes_per_line).max(1);
+        let grid_height = self
+            .columns
+            .iter()
+            .map(|column| (column.rows.iter().map(lines).sum::<usize>() + 1) * LINE_HEIGHT)
+            .max()
+            .unwrap_or_default();
+        let notes = self.footnotes.len() + usize::from(self.has_estimates());
+        let width = (COLUMN_WIDTH + 10) * self.columns.len().max(1) + 10;
+        let height = HEADER_HEIGHT + grid_height + (notes + 1) * LINE_HEIGHT;
+
+        let mut svg = String::new();
+        let _ = write!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+             viewBox=\"0 0 {width} {height}\" font-family=\"sans-serif\" font-size=\"13\">\n\
+             <rect width=\"100%\" height=\"100%\" fill=\"#fff\"/>\n\
+             <text x=\"10\" y=\"28\" font-size=\"22\" font-weight=\"bold\">{}</text>\n\
+             <text x=\"10\" y=\"50\">Towards {} – week of {}</text>\n",
+            escape(&self.title()),
+            escape(&self.destination),
+            self.week_of
+        );
+        for (idx, column) in self.columns.iter().enumerate() {
+            let x = 10 + idx * (COLUMN_WIDTH + 10);
+            let mut y = HEADER_HEIGHT;
+            let _ = write!(
+                svg,
+                "<rect x=\"{x}\" y=\"{y}\" width=\"{COLUMN_WIDTH}\" height=\"{LINE_HEIGHT}\" \
+                 fill=\"#000\"/>\n<text x=\"{}\" y=\"{}\" fill=\"#fff\" font-weight=\"bold\">{}</text>\n",
+                x + 5,
+                y + LINE_HEIGHT - 5,
+                column.title
+            );
+            y += LINE_HEIGHT;
+            for row in &column.rows {
+                let row_height = lines(row) * LINE_HEIGHT;
+                let _ = write!(
+                    svg,
+                    "<rect x=\"{x}\" y=\"{y}\" width=\"{COLUMN_WIDTH}\" height=\"{row_height}\" \
+                     fill=\"none\" stroke=\"#000\"/>\n<rect x=\"{x}\" y=\"{y}\" width=\"{HOUR_WIDTH}\" \
+                     height=\"{row_height}\" fill=\"#eee\" stroke=\"#000\"/>\n\
+                     <text x=\"{}\" y=\"{}\" font-weight=\"bold\">{:02}</text>\n",
+                    x + 6,
+                    y + LINE_HEIGHT - 5,
+                    row.hour
+                );
+                for (position, departure) in row.departures.iter().enumerate() {
+                    let _ = write!(
+                        svg,
+                        "<text x=\"{}\" y=\"{}\">{}{:02}",
+                        x + HOUR_WIDTH + 6 + (position % minutes_per_line) * MINUTE_WIDTH,
+                        y + (position / minutes_per_line + 1) * LINE_HEIGHT - 5,
+                        if departure.exact_time { "" } else { "~" },
+                        departure.minute
+                    );
+                    if let Some(mark) = &departure.footnote {
+                        let _ = write!(
+                            svg,
+                            "<tspan font-size=\"9\" baseline-shift=\"super\">{mark}</tspan>"
+                        );
+                    }
+                    svg.push_str("</text>\n");
+                }
+                y += row_height;
+            }
+        }
+        let mut y = HEADER_HEIGHT + grid_height + LINE_HEIGHT;
+        for footnote in &self.footnotes {
+            let _ = writeln!(
+                svg,
+                "<text x=\"10\" y=\"{y}\"><tspan font-weight=\"bold\">{}</tspan> {}</text>",
+                footnote.mark,
+                escape(&footnote.text)
+            );
+            y += LINE_HEIGHT;
+        }
+        if self.has_estimates() {
+            let _ = writeln!(
+                svg,
+                "<text x=\"10\" y=\"{y}\"><tspan font-weight=\"bold\">~</tspan> estimated time, \
+                 buses run at the stated frequency</text>"
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_util::*;
+    use crate::*;
+
+    fn add_journey(tt: &mut TimeTable, service_id: &str, stops: &[(u32, u32, &str)]) {
+        tt.journeys.push(Journey {
+            direction_id: Some(Direction::Outbound),
+            ..trip(&format!("t{}", tt.journeys.len()), service_id, stops)
+        });
+    }
+
+    fn sample_tt() -> TimeTable {
+        let mut tt = TimeTable::new();
+        add_stops(
+            &mut tt,
+            &[
+                ("eglise", "Église"),
+                ("potato", "Potato Factory"),
+                ("gare", "Gare"),
+            ],
+        );
+        for (service_id, weekdays) in [
+            ("wd1", WeekdayFlags::WORKDAYS),
+            ("wed1", WeekdayFlags::WEDNESDAY),
+            ("sat1", WeekdayFlags::SATURDAY),
+        ] {
+            add_service(&mut tt, service_id, weekdays);
+        }
+        for (hour, minute) in [(7, 5), (7, 35), (8, 5)] {
+            add_journey(
+                &mut tt,
+                "wd1",
+                &[(hour, minute, "eglise"), (hour, minute + 20, "gare")],
+            );
+        }
+        add_journey(
+            &mut tt,
+            "wd1",
+            &[(8, 10, "eglise"), (8, 20, "potato"), (8, 40, "gare")],
+        );
+        add_journey(&mut tt, "wed1", &[(12, 0, "eglise"), (12, 20, "gare")]);
+        add_journey(&mut tt, "sat1", &[(9, 0, "eglise"), (9, 20, "gare")]);
+        // Night bus on Saturdays, leaving after midnight.
+        add_journey(&mut tt, "sat1", &[(24, 30, "eglise"), (24, 50, "gare")]);
+        tt
+    }
+
+    #[test]
+    fn grid() {
+        let tt = sample_tt();
+        let week_of = NaiveDate::from_ymd_opt(2024, 3, 6).unwrap();
+        let grid = tt.get_printable_grid("Église", None, Some(Direction::Outbound), week_of);
+        assert_eq!(grid.week_of, NaiveDate::from_ymd_opt(2024, 3, 4).unwrap());
+        assert_eq!(grid.destination, "Gare");
+        let eglise = StopId::from("eglise");
+        let by_id = tt.get_printable_grid(&eglise, None, Some(Direction::Outbound), week_of);
+        assert_eq!(by_id.stop_name, "Église");
+        assert_eq!(by_id.columns.len(), grid.columns.len());
+        // The night bus leaves on Sunday, with Saturday's service.
+        let titles: Vec<_> = grid.columns.iter().map(|column| column.title).collect();
+        assert_eq!(titles, ["Monday to Friday", "Saturday"]);
+        let saturday_hours: Vec<_> = grid.columns[1].rows.iter().map(|row| row.hour).collect();
+        assert_eq!(saturday_hours, [9, 0]);
+        let workdays = &grid.columns[0];
+        let hours: Vec<_> = workdays.rows.iter().map(|row| row.hour).collect();
+        assert_eq!(hours, [7, 8, 12]);
+        let minutes: Vec<_> = workdays.rows[1]
+            .departures
+            .iter()
+            .map(|departure| (departure.minute, departure.footnote.as_deref()))
+            .collect();
+        assert_eq!(minutes, [(5, None), (10, Some("a"))]);
+        assert_eq!(
+            workdays.rows[2].departures[0].footnote.as_deref(),
+            Some("b")
+        );
+        assert_eq!(grid.footnotes[0].text, "via Potato Factory");
+        assert_eq!(grid.footnotes[1].text, "only on Wednesday");
+
+        let html = grid.to_html();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<span>10<sup>a</sup></span>"));
+        let svg = grid.to_svg();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("only on Wednesday"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn night_hours_last() {
+        let mut tt = sample_tt();
+        add_journey(&mut tt, "wd1", &[(24, 15, "eglise"), (24, 35, "gare")]);
+        let week_of = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap();
+        let grid = tt.get_printable_grid("Église", None, None, week_of);
+        let hours: Vec<_> = grid.columns[0].rows.iter().map(|row| row.hour).collect();
+        assert_eq!(hours, [7, 8, 12, 0]);
+        // Runs after each workday, Friday's included, so it needs no footnote.
+        let night = &grid.columns[0].rows.last().unwrap().departures[0];
+        assert_eq!(night.minute, 15);
+        assert_eq!(night.footnote, None);
+        // Friday night's bus isn't Saturday's.
+        assert!(grid.columns[1]
+            .rows
+            .iter()
+            .flat_map(|row| &row.departures)
+            .all(|departure| departure.minute != 15));
+    }
+}
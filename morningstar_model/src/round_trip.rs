@@ -0,0 +1,213 @@
+//! Going somewhere and coming back on the same day, with time to spend there.
+
+use crate::{DatedStopTime, StopSelector, TimeTable};
+use chrono::{DateTime, Duration};
+
+/// How long to stay at the destination, which decides the return journey of each outbound one.
+#[derive(Debug, Clone, Copy)]
+pub enum Stay {
+    /// Return on the first journey leaving at least this long after arriving.
+    AtLeast(Duration),
+    /// Return on the journey leaving closest to this long after arriving, the earlier one on
+    /// a tie.
+    About(Duration),
+}
+
+#[derive(Debug, Clone)]
+pub struct RoundTrip<'a> {
+    /// Boarding at the origin and alighting at the destination.
+    pub outbound: (DatedStopTime<'a>, DatedStopTime<'a>),
+    /// Boarding at the destination and alighting back at the origin.
+    pub inbound: (DatedStopTime<'a>, DatedStopTime<'a>),
+    /// Time between arriving at the destination and leaving it.
+    pub stay: Duration,
+    /// Whether the return journey is the last one leaving the destination that day.
+    pub last_return: bool,
+}
+
+impl TimeTable {
+    /// Round trips from `origin` to `destination` and back, both ways leaving on provided day.
+    /// Each outbound journey is paired with the return journey matching `stay`, outbound
+    /// journeys without one are left out. Stops are given by exact name, id or stop area, round
+    /// trips are in outbound departure order.
+    pub fn get_day_round_trips<'a>(
+        &'a self,
+        day: &'a chrono::NaiveDate,
+        origin: impl Into<StopSelector<'a>>,
+        destination: impl Into<StopSelector<'a>>,
+        stay: Stay,
+        route_id: Option<&'a str>,
+    ) -> Vec<RoundTrip<'a>> {
+        let (origin, destination) = (origin.into(), destination.into());
+        let tz = self.tz();
+        let mut outbound: Vec<_> = self
+            .get_day_stoptimes_from_a_to_b(day, origin, destination, route_id)
+            .map(|(board, alight)| (board.departure_datetime(&tz), board, alight))
+            .collect();
+        outbound.sort_by_key(|(departure, _, _)| *departure);
+        let mut inbound: Vec<_> = self
+            .get_day_stoptimes_from_a_to_b(day, destination, origin, route_id)
+            .map(|(board, alight)| (board.departure_datetime(&tz), board, alight))
+            .collect();
+        inbound.sort_by_key(|(departure, _, _)| *departure);
+        let Some(last_departure) = inbound.last().map(|(departure, _, _)| *departure) else {
+            return vec![];
+        };
+        outbound
+            .into_iter()
+            .filter_map(|(_, board, alight)| {
+                let arrival = alight.arrival_datetime(&tz);
+                let (departure, return_board, return_alight) =
+                    pick_return(&inbound, arrival, stay)?;
+                Some(RoundTrip {
+                    outbound: (board, alight),
+                    inbound: (*return_board, *return_alight),
+                    stay: *departure - arrival,
+                    last_return: *departure == last_departure,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Return journey to take after arriving at `arrival`, among journeys in departure order.
+fn pick_return<'r, 'a, Tz: chrono::TimeZone>(
+    inbound: &'r [(DateTime<Tz>, DatedStopTime<'a>, DatedStopTime<'a>)],
+    arrival: DateTime<Tz>,
+    stay: Stay,
+) -> Option<&'r (DateTime<Tz>, DatedStopTime<'a>, DatedStopTime<'a>)> {
+    match stay {
+        Stay::AtLeast(min_stay) => {
+            let earliest = arrival + min_stay;
+            inbound
+                .iter()
+                .find(|(departure, _, _)| *departure >= earliest)
+        }
+        Stay::About(desired_stay) => {
+            let target = arrival.clone() + desired_stay;
+            inbound
+                .iter()
+                .filter(|(departure, _, _)| *departure >= arrival)
+                .min_by_key(|(departure, _, _)| (departure.clone() - target.clone()).abs())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+    use crate::*;
+
+    /// Two buses to town in the morning, three back during the day.
+    fn sample_tt() -> TimeTable {
+        let mut tt = TimeTable::new();
+        tt.timezone = "Europe/Paris".to_owned();
+        add_stops(&mut tt, &[("village", "Village"), ("town", "Town")]);
+        add_service(&mut tt, "wd1", WeekdayFlags::WORKDAYS);
+        tt.journeys
+            .push(trip("out1", "wd1", &[(8, 0, "village"), (8, 30, "town")]));
+        tt.journeys
+            .push(trip("out2", "wd1", &[(10, 0, "village"), (10, 30, "town")]));
+        tt.journeys.push(trip(
+            "back3",
+            "wd1",
+            &[(17, 0, "town"), (17, 30, "village")],
+        ));
+        tt.journeys
+            .push(trip("back1", "wd1", &[(9, 0, "town"), (9, 30, "village")]));
+        tt.journeys.push(trip(
+            "back2",
+            "wd1",
+            &[(12, 0, "town"), (12, 30, "village")],
+        ));
+        tt.sort_journeys_and_stops();
+        tt
+    }
+
+    fn trips(round_trips: &[RoundTrip]) -> Vec<(String, String, i64, bool)> {
+        round_trips
+            .iter()
+            .map(|round_trip| {
+                (
+                    round_trip.outbound.0.journey.trip_id.clone(),
+                    round_trip.inbound.0.journey.trip_id.clone(),
+                    round_trip.stay.num_minutes(),
+                    round_trip.last_return,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn minimum_stay() {
+        let tt = sample_tt();
+        let day = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap();
+        let round_trips = tt.get_day_round_trips(
+            &day,
+            "Village",
+            "Town",
+            Stay::AtLeast(Duration::hours(1)),
+            None,
+        );
+        assert_eq!(
+            trips(&round_trips),
+            [
+                ("out1".into(), "back2".into(), 210, false),
+                ("out2".into(), "back2".into(), 90, false),
+            ]
+        );
+        let (village, town) = (StopId::from("village"), StopId::from("town"));
+        let by_id = tt.get_day_round_trips(
+            &day,
+            &village,
+            &town,
+            Stay::AtLeast(Duration::hours(1)),
+            None,
+        );
+        assert_eq!(trips(&by_id), trips(&round_trips));
+        // Too long a stay to make it back after the second bus.
+        let round_trips = tt.get_day_round_trips(
+            &day,
+            "Village",
+            "Town",
+            Stay::AtLeast(Duration::hours(8)),
+            None,
+        );
+        assert_eq!(
+            trips(&round_trips),
+            [("out1".into(), "back3".into(), 510, true)]
+        );
+    }
+
+    #[test]
+    fn desired_stay() {
+        let tt = sample_tt();
+        let day = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap();
+        let round_trips = tt.get_day_round_trips(
+            &day,
+            "Village",
+            "Town",
+            Stay::About(Duration::hours(7)),
+            None,
+        );
+        assert_eq!(
+            trips(&round_trips),
+            [
+                ("out1".into(), "back3".into(), 510, true),
+                ("out2".into(), "back3".into(), 390, true),
+            ]
+        );
+        // No buses on weekends.
+        let sunday = NaiveDate::from_ymd_opt(2024, 3, 3).unwrap();
+        assert!(tt
+            .get_day_round_trips(
+                &sunday,
+                "Village",
+                "Town",
+                Stay::About(Duration::zero()),
+                None
+            )
+            .is_empty());
+    }
+}
@@ -0,0 +1,194 @@
+//! Scheduled travel time between two stops, by hour of departure and type of day.
+//!
+//! Roads are busier at some hours than at others, and timetables allow for it: the same trip
+//! may be scheduled to take 12 minutes at 2pm and 20 at 8am. Each journey is counted once per
+//! type of day it runs on, however many dates it runs on.
+
+use crate::{holidays, StopSelector, TimeTable};
+use chrono::{Datelike, Duration, NaiveDate, Timelike, Weekday};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+
+/// Type of day, as timetables tell them apart. Public holidays get the Sunday service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum DayType {
+    Workday,
+    Saturday,
+    Sunday,
+}
+
+impl DayType {
+    pub fn of(date: NaiveDate) -> Self {
+        if holidays::public_holiday(date).is_some() {
+            return Self::Sunday;
+        }
+        match date.weekday() {
+            Weekday::Sat => Self::Saturday,
+            Weekday::Sun => Self::Sunday,
+            _ => Self::Workday,
+        }
+    }
+}
+
+impl Display for DayType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Workday => write!(f, "Monday to Friday"),
+            Self::Saturday => write!(f, "Saturday"),
+            Self::Sunday => write!(f, "Sunday and public holidays"),
+        }
+    }
+}
+
+/// Scheduled travel times of the journeys leaving within an hour on a type of day.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunTimes {
+    pub day_type: DayType,
+    /// Hour the journeys leave the first stop, in the feed's timezone.
+    pub hour: u32,
+    /// Number of journeys the figures come from.
+    pub journeys: usize,
+    pub min: Duration,
+    /// Middle travel time, halfway between the two middle ones for an even number of journeys.
+    pub median: Duration,
+    pub max: Duration,
+}
+
+impl RunTimes {
+    fn new(day_type: DayType, hour: u32, mut run_times: Vec<Duration>) -> Self {
+        run_times.sort();
+        let middle = run_times.len() / 2;
+        let median = if run_times.len().is_multiple_of(2) {
+            (run_times[middle - 1] + run_times[middle]) / 2
+        } else {
+            run_times[middle]
+        };
+        Self {
+            day_type,
+            hour,
+            journeys: run_times.len(),
+            min: run_times[0],
+            median,
+            max: run_times[run_times.len() - 1],
+        }
+    }
+}
+
+impl TimeTable {
+    /// Scheduled travel times from `from` to `to` for journeys leaving on provided dates, by
+    /// type of day then hour of departure. Stops are given by exact name, id or stop area, only
+    /// journeys that can be boarded at `from` and left at `to` count.
+    pub fn get_run_times<'a>(
+        &'a self,
+        from: impl Into<StopSelector<'a>>,
+        to: impl Into<StopSelector<'a>>,
+        route_id: Option<&'a str>,
+        dates: RangeInclusive<NaiveDate>,
+    ) -> Vec<RunTimes> {
+        let (from, to) = (from.into(), to.into());
+        let tz = self.tz();
+        let mut seen = HashSet::new();
+        let mut buckets: BTreeMap<(DayType, u32), Vec<Duration>> = BTreeMap::new();
+        let days: Vec<_> = dates
+            .start()
+            .iter_days()
+            .take_while(|day| day <= dates.end())
+            .collect();
+        for day in &days {
+            let day_type = DayType::of(*day);
+            for (board, alight) in self.get_day_stoptimes_from_a_to_b(day, from, to, route_id) {
+                if !seen.insert((day_type, board.journey.journey.trip_id.as_str())) {
+                    continue;
+                }
+                let run_time = alight.arrival_datetime(&tz) - board.departure_datetime(&tz);
+                buckets
+                    .entry((day_type, board.departure_time_of_day(&tz).hour()))
+                    .or_default()
+                    .push(run_time);
+            }
+        }
+        buckets
+            .into_iter()
+            .map(|((day_type, hour), run_times)| RunTimes::new(day_type, hour, run_times))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+    use crate::*;
+
+    fn add_journey(tt: &mut TimeTable, trip_id: &str, service_id: &str, times: [(u32, u32); 2]) {
+        let [(board_hour, board_minute), (alight_hour, alight_minute)] = times;
+        tt.journeys.push(trip(
+            trip_id,
+            service_id,
+            &[
+                (board_hour, board_minute, "village"),
+                (alight_hour, alight_minute, "town"),
+            ],
+        ));
+    }
+
+    fn sample_tt() -> TimeTable {
+        let mut tt = TimeTable::new();
+        tt.timezone = "Europe/Paris".to_owned();
+        add_stops(&mut tt, &[("village", "Village"), ("town", "Town")]);
+        add_service(&mut tt, "wd1", WeekdayFlags::WORKDAYS);
+        add_service(&mut tt, "sat1", WeekdayFlags::SATURDAY);
+        add_journey(&mut tt, "rush1", "wd1", [(8, 0), (8, 20)]);
+        add_journey(&mut tt, "rush2", "wd1", [(8, 30), (8, 55)]);
+        add_journey(&mut tt, "rush3", "wd1", [(8, 50), (9, 6)]);
+        add_journey(&mut tt, "rush4", "wd1", [(8, 55), (9, 13)]);
+        add_journey(&mut tt, "noon", "wd1", [(14, 0), (14, 12)]);
+        add_journey(&mut tt, "sat", "sat1", [(8, 0), (8, 14)]);
+        tt.sort_journeys_and_stops();
+        tt
+    }
+
+    #[test]
+    fn run_times() {
+        let tt = sample_tt();
+        let week = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap()
+            ..=NaiveDate::from_ymd_opt(2024, 3, 17).unwrap();
+        let run_times = tt.get_run_times("Village", "Town", None, week.clone());
+        let minutes = |duration: Duration| duration.num_minutes();
+        let summary: Vec<_> = run_times
+            .iter()
+            .map(|run_times| {
+                (
+                    run_times.day_type,
+                    run_times.hour,
+                    run_times.journeys,
+                    minutes(run_times.min),
+                    minutes(run_times.median),
+                    minutes(run_times.max),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (DayType::Workday, 8, 4, 16, 19, 25),
+                (DayType::Workday, 14, 1, 12, 12, 12),
+                (DayType::Saturday, 8, 1, 14, 14, 14),
+            ]
+        );
+        // Journeys only go to town.
+        assert!(tt.get_run_times("Town", "Village", None, week).is_empty());
+    }
+
+    #[test]
+    fn day_types() {
+        let day = |month, day| DayType::of(NaiveDate::from_ymd_opt(2024, month, day).unwrap());
+        assert_eq!(day(3, 4), DayType::Workday);
+        assert_eq!(day(3, 9), DayType::Saturday);
+        assert_eq!(day(3, 10), DayType::Sunday);
+        // Labour Day, a Wednesday.
+        assert_eq!(day(5, 1), DayType::Sunday);
+    }
+}
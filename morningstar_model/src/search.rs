@@ -0,0 +1,238 @@
+//! Stop names matching what a user typed, accents, case, word order and typos aside.
+//!
+//! Names and queries are folded to lowercase ASCII words. Each query word is matched to the name
+//! word it fits best: exactly, as its beginning so that results show up while typing, or a
+//! letter or two off for longer words. A name's score is how well the query words fit, with a
+//! bonus for covering the whole name so that "gare" ranks "Gare" above "Gare Routière".
+
+use crate::TimeTable;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// Names scoring less than this don't match.
+const MIN_SCORE: f64 = 0.5;
+
+/// Weight in the score of how much of the name the query covers, the rest being how well the
+/// query words fit.
+const COVERAGE_WEIGHT: f64 = 0.2;
+
+/// A stop name and how well it matches the query, from 0 to 1.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StopMatch {
+    pub name: String,
+    pub score: f64,
+}
+
+/// Folded words of stop names, to match queries against.
+#[derive(Debug, Clone, Default)]
+pub struct SearchIndex {
+    /// Names with their folded words, sorted by name.
+    entries: Vec<(String, Vec<String>)>,
+}
+
+impl SearchIndex {
+    /// Index of provided names, duplicates being indexed once.
+    pub fn new<'a>(names: impl IntoIterator<Item = &'a str>) -> Self {
+        let names: BTreeSet<_> = names.into_iter().collect();
+        Self {
+            entries: names
+                .into_iter()
+                .map(|name| (name.to_owned(), words(name)))
+                .collect(),
+        }
+    }
+
+    /// Names matching the query, best first, at most `limit` of them.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<StopMatch> {
+        let query = words(query);
+        let mut matches: Vec<_> = self
+            .entries
+            .iter()
+            .filter_map(|(name, name_words)| {
+                Some(StopMatch {
+                    name: name.clone(),
+                    score: score_words(&query, name_words)?,
+                })
+            })
+            .collect();
+        // Entries are sorted by name, which the stable sort keeps among equal scores.
+        matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+        matches.truncate(limit);
+        matches
+    }
+
+    /// Best matching name, if any.
+    pub fn best_match(&self, query: &str) -> Option<String> {
+        self.search(query, 1).pop().map(|found| found.name)
+    }
+}
+
+impl TimeTable {
+    /// Stop names matching the query, best first, at most `limit` of them.
+    pub fn search_stops(&self, query: &str, limit: usize) -> Vec<StopMatch> {
+        self.index().search().search(query, limit)
+    }
+
+    /// Name of the stop the query most likely means: the query itself when a stop has exactly
+    /// that name, the best match otherwise.
+    pub fn find_stop_name(&self, query: &str) -> Option<String> {
+        if !self.get_stop_ids_named(query).is_empty() {
+            return Some(query.to_owned());
+        }
+        self.index().search().best_match(query)
+    }
+}
+
+/// How well the query matches the name, `None` when it doesn't. An empty query matches nothing.
+pub fn score(query: &str, name: &str) -> Option<f64> {
+    score_words(&words(query), &words(name))
+}
+
+fn score_words(query: &[String], name: &[String]) -> Option<f64> {
+    if query.is_empty() || name.is_empty() {
+        return None;
+    }
+    let mut used = vec![false; name.len()];
+    let mut fit = 0.0;
+    for query_word in query {
+        let best = name
+            .iter()
+            .enumerate()
+            .filter(|(position, _)| !used[*position])
+            .map(|(position, name_word)| (position, word_fit(query_word, name_word)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+        if let Some((position, word_fit)) = best.filter(|(_, word_fit)| *word_fit > 0.0) {
+            used[position] = true;
+            fit += word_fit;
+        }
+    }
+    let fit = fit / query.len() as f64;
+    let coverage = used.iter().filter(|used| **used).count() as f64 / name.len() as f64;
+    let score = (1.0 - COVERAGE_WEIGHT) * fit + COVERAGE_WEIGHT * coverage;
+    (score >= MIN_SCORE).then_some(score)
+}
+
+/// How well a query word fits a name word, 0 when it doesn't.
+fn word_fit(query: &str, name: &str) -> f64 {
+    if query == name {
+        return 1.0;
+    }
+    if name.starts_with(query) {
+        // Longer typed prefixes are more telling.
+        return 0.7 + 0.2 * query.len() as f64 / name.len() as f64;
+    }
+    let tolerance = typo_tolerance(query);
+    if tolerance == 0 {
+        return 0.0;
+    }
+    let distance = edit_distance(query, name);
+    if distance <= tolerance {
+        return 0.7 - 0.1 * distance as f64;
+    }
+    // Typo in a word still being typed.
+    let prefix: String = name.chars().take(query.chars().count()).collect();
+    if edit_distance(query, &prefix) <= tolerance {
+        0.5
+    } else {
+        0.0
+    }
+}
+
+/// Letters a word may be off by, none for short words which would match too many others.
+fn typo_tolerance(word: &str) -> usize {
+    match word.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Lowercase ASCII words of the text, accents removed and punctuation as separators.
+fn words(text: &str) -> Vec<String> {
+    unidecode::unidecode(text)
+        .to_lowercase()
+        .split(|char: char| !char.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Edits turning one word into the other, swapping two neighbouring letters counting as one.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<_>, Vec<_>) = (a.chars().collect(), b.chars().collect());
+    // distances[i][j] is the distance between a[..i] and b[..j].
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + substitution);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn index() -> SearchIndex {
+        SearchIndex::new([
+            "Église",
+            "Gare",
+            "Gare Routière",
+            "Marché",
+            "Saint-Rémy - Mairie",
+            "Gare",
+        ])
+    }
+
+    fn names(matches: Vec<StopMatch>) -> Vec<String> {
+        matches.into_iter().map(|found| found.name).collect()
+    }
+
+    #[test]
+    fn search() {
+        let index = index();
+        assert_eq!(names(index.search("gare", 5)), ["Gare", "Gare Routière"]);
+        assert_eq!(index.best_match("eglise").as_deref(), Some("Église"));
+        // Typo, word order and prefix of a word being typed.
+        assert_eq!(index.best_match("marhce").as_deref(), Some("Marché"));
+        assert_eq!(
+            index.best_match("mairie st remy").as_deref(),
+            Some("Saint-Rémy - Mairie")
+        );
+        assert_eq!(index.best_match("rout").as_deref(), Some("Gare Routière"));
+        assert!(index.search("potato", 5).is_empty());
+        assert!(index.search("", 5).is_empty());
+        assert_eq!(index.search("g", 1).len(), 1);
+    }
+
+    #[test]
+    fn timetable() {
+        let tt = crate::read_timetable(include_bytes!("../fixtures/timetable_v1.ron").as_slice())
+            .unwrap();
+        assert_eq!(tt.find_stop_name("Gare").as_deref(), Some("Gare"));
+        assert_eq!(tt.find_stop_name("marche").as_deref(), Some("Marché"));
+        assert_eq!(tt.search_stops("EGLISE", 3)[0].name, "Église");
+    }
+
+    #[test]
+    fn distances() {
+        assert_eq!(edit_distance("gare", "gare"), 0);
+        assert_eq!(edit_distance("gare", "agre"), 1);
+        assert_eq!(edit_distance("mairie", "marie"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+}
@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+const SECONDS_PER_DAY: u32 = 24 * 60 * 60;
+
+/// Time of a stop relative to its service day, as GTFS defines it: seconds elapsed since "noon
+/// minus 12h" of the service date. Trips that run past midnight keep counting, so a stop at
+/// 00:30 the next morning is `24:30:00` and still belongs to the service day the trip started on.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
+pub struct ServiceTime(u32);
+
+impl ServiceTime {
+    pub fn from_seconds(seconds: u32) -> Self {
+        Self(seconds)
+    }
+
+    /// Hours can go past 23 for stops served after midnight.
+    pub fn from_hms(hour: u32, minute: u32, second: u32) -> Self {
+        Self(hour * 3600 + minute * 60 + second)
+    }
+
+    pub fn seconds(&self) -> u32 {
+        self.0
+    }
+
+    /// Hours since the start of the service day, 24 and over after midnight.
+    pub fn hour(&self) -> u32 {
+        self.0 / 3600
+    }
+
+    pub fn minute(&self) -> u32 {
+        (self.0 / 60) % 60
+    }
+
+    pub fn second(&self) -> u32 {
+        self.0 % 60
+    }
+
+    /// Number of calendar days between the service date and the day this time falls on.
+    pub fn days_after_service_date(&self) -> u32 {
+        self.0 / SECONDS_PER_DAY
+    }
+
+    /// Wall clock time on the calendar day this time falls on, as printed in timetables. It is
+    /// an hour off the actual time before clocks change on the days they do, `datetime_on`
+    /// gives the actual instant.
+    pub fn time_of_day(&self) -> chrono::NaiveTime {
+        chrono::NaiveTime::from_num_seconds_from_midnight_opt(self.0 % SECONDS_PER_DAY, 0)
+            .expect("seconds modulo a day to be a valid time")
+    }
+
+    /// Calendar date this time falls on, given the date of its service day, as printed in
+    /// timetables. Like `time_of_day`, it ignores clock changes.
+    pub fn calendar_date(&self, service_date: chrono::NaiveDate) -> chrono::NaiveDate {
+        service_date + chrono::Days::new(self.days_after_service_date() as u64)
+    }
+
+    /// Instant this time falls on, given the date of its service day and the feed's timezone.
+    /// GTFS counts from noon minus 12h rather than midnight so that days when clocks change
+    /// still line up.
+    pub fn datetime_on<Tz: chrono::TimeZone>(
+        &self,
+        service_date: chrono::NaiveDate,
+        tz: &Tz,
+    ) -> chrono::DateTime<Tz> {
+        let noon = service_date
+            .and_hms_opt(12, 0, 0)
+            .expect("noon to be a valid time");
+        let noon = tz
+            .from_local_datetime(&noon)
+            .earliest()
+            .unwrap_or_else(|| tz.from_utc_datetime(&noon));
+        noon - chrono::Duration::hours(12) + chrono::Duration::seconds(self.0 as i64)
+    }
+
+    pub fn signed_duration_since(&self, rhs: ServiceTime) -> chrono::Duration {
+        chrono::Duration::seconds(self.0 as i64 - rhs.0 as i64)
+    }
+}
+
+impl From<chrono::NaiveTime> for ServiceTime {
+    fn from(value: chrono::NaiveTime) -> Self {
+        use chrono::Timelike;
+        Self(value.num_seconds_from_midnight())
+    }
+}
+
+impl Display for ServiceTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}:{:02}",
+            self.hour(),
+            self.minute(),
+            self.second()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::prelude::*;
+
+    #[test]
+    fn past_midnight() {
+        let time = ServiceTime::from_hms(25, 10, 0);
+        assert_eq!(time.hour(), 25);
+        assert_eq!(time.minute(), 10);
+        assert_eq!(time.days_after_service_date(), 1);
+        assert_eq!(
+            time.time_of_day(),
+            NaiveTime::from_hms_opt(1, 10, 0).unwrap()
+        );
+        let service_date = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        assert_eq!(
+            time.calendar_date(service_date),
+            NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()
+        );
+        assert_eq!(time.to_string(), "25:10:00");
+    }
+
+    #[test]
+    fn clocks_change() {
+        let tz = chrono_tz::Europe::Paris;
+        // Clocks go forward at 02:00 that night, so the service day starts at 23:00 the evening
+        // before and times before the change are an hour ahead of the wall clock.
+        let service_date = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+        let datetime = ServiceTime::from_hms(1, 0, 0).datetime_on(service_date, &tz);
+        assert_eq!(datetime.to_rfc3339(), "2024-03-31T00:00:00+01:00");
+        let datetime = ServiceTime::from_hms(4, 30, 0).datetime_on(service_date, &tz);
+        assert_eq!(datetime.to_rfc3339(), "2024-03-31T04:30:00+02:00");
+        let datetime = ServiceTime::from_hms(13, 0, 0).datetime_on(service_date, &tz);
+        assert_eq!(datetime.to_rfc3339(), "2024-03-31T13:00:00+02:00");
+        let datetime = ServiceTime::from_hms(24, 15, 0).datetime_on(service_date, &tz);
+        assert_eq!(datetime.to_rfc3339(), "2024-04-01T00:15:00+02:00");
+    }
+
+    #[test]
+    fn same_day() {
+        let time = ServiceTime::from(NaiveTime::from_hms_opt(7, 45, 30).unwrap());
+        assert_eq!(time, ServiceTime::from_hms(7, 45, 30));
+        assert_eq!(time.days_after_service_date(), 0);
+        let service_date = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        assert_eq!(time.calendar_date(service_date), service_date);
+    }
+}
@@ -0,0 +1,220 @@
+//! Facts about the service at a stop: when it starts and ends, how many buses leave each hour
+//! and the longest wait between two of them.
+//!
+//! Figures are given for each type of day, from the day of that type with the most departures
+//! in the week looked at, so that services running on some workdays only are counted. Days are
+//! service days: departures after midnight count for the day their journey started on.
+
+use crate::run_times::DayType;
+use crate::{ServiceTime, StopSelector, TimeTable};
+use chrono::{Datelike, NaiveDate, NaiveTime};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Longest time without a departure between the first and the last one of the day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Gap {
+    /// Departure before the gap.
+    pub from: NaiveTime,
+    /// Departure ending the gap.
+    pub to: NaiveTime,
+    pub minutes: i64,
+}
+
+/// Service at a stop on a type of day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StopStats {
+    pub day_type: DayType,
+    /// Service day the figures come from.
+    pub date: NaiveDate,
+    pub departures: usize,
+    pub first_departure: NaiveTime,
+    /// Wall clock time, which is on the next calendar day when the service runs past midnight.
+    pub last_departure: NaiveTime,
+    /// Number of departures by hour of the service day, hours without any left out. Hours from
+    /// 24 on are after midnight, 24 being from 00:00 to 00:59 on the next calendar day.
+    pub departures_per_hour: BTreeMap<u32, usize>,
+    /// `None` when there is a single departure.
+    pub longest_gap: Option<Gap>,
+}
+
+impl StopStats {
+    /// Figures from departure times sorted in time order, `None` when there are none.
+    fn new<Tz: chrono::TimeZone>(
+        day_type: DayType,
+        date: NaiveDate,
+        times: &[ServiceTime],
+        tz: &Tz,
+    ) -> Option<Self> {
+        let mut departures_per_hour = BTreeMap::new();
+        for time in times {
+            *departures_per_hour.entry(time.hour()).or_default() += 1;
+        }
+        let datetime = |time: &ServiceTime| time.datetime_on(date, tz);
+        let longest_gap = times
+            .windows(2)
+            .map(|pair| (datetime(&pair[0]), datetime(&pair[1])))
+            .max_by_key(|(from, to)| to.clone() - from.clone())
+            .map(|(from, to)| Gap {
+                from: from.time(),
+                to: to.time(),
+                minutes: (to - from).num_minutes(),
+            });
+        Some(Self {
+            day_type,
+            date,
+            departures: times.len(),
+            first_departure: datetime(times.first()?).time(),
+            last_departure: datetime(times.last()?).time(),
+            departures_per_hour,
+            longest_gap,
+        })
+    }
+}
+
+impl TimeTable {
+    /// Service at a stop, given by exact name, id or stop area, on each type of day of the week
+    /// starting on the Monday of `week_of`. Types of day without departures are left out.
+    pub fn get_stop_stats<'a>(
+        &'a self,
+        stop: impl Into<StopSelector<'a>>,
+        route_id: Option<&'a str>,
+        week_of: NaiveDate,
+    ) -> Vec<StopStats> {
+        let stop_ids = self.get_stop_ids(stop.into());
+        let tz = self.tz();
+        let monday = week_of - chrono::Days::new(week_of.weekday().num_days_from_monday() as u64);
+        let week: Vec<_> = monday.iter_days().take(7).collect();
+        let mut busiest: BTreeMap<DayType, (NaiveDate, Vec<ServiceTime>)> = BTreeMap::new();
+        for day in &week {
+            let mut times: Vec<_> = self
+                .get_journeys_for_service_date(day, route_id)
+                .flat_map(|journey| {
+                    let terminus = journey.stops.len().saturating_sub(1);
+                    journey
+                        .all_stops()
+                        .enumerate()
+                        .filter(move |(position, _)| *position != terminus)
+                        .map(|(_, stop)| stop)
+                })
+                .filter(|stop| stop.can_board() && stop_ids.contains(&stop.stop_id))
+                .map(|stop| stop.departure_time)
+                .collect();
+            times.sort();
+            let entry = busiest.entry(DayType::of(*day)).or_default();
+            if times.len() > entry.1.len() {
+                *entry = (*day, times);
+            }
+        }
+        busiest
+            .into_iter()
+            .filter_map(|(day_type, (date, times))| StopStats::new(day_type, date, &times, &tz))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+    use crate::*;
+
+    fn add_journey(tt: &mut TimeTable, service_id: &str, hour: u32, minute: u32) {
+        tt.journeys.push(trip(
+            &format!("{service_id}-{hour}:{minute}"),
+            service_id,
+            &[(hour, minute, "village"), (hour, minute + 10, "town")],
+        ));
+    }
+
+    fn sample_tt() -> TimeTable {
+        let mut tt = TimeTable::new();
+        tt.timezone = "Europe/Paris".to_owned();
+        add_stops(&mut tt, &[("village", "Village"), ("town", "Town")]);
+        add_service(&mut tt, "wd1", WeekdayFlags::WORKDAYS);
+        add_service(&mut tt, "sat1", WeekdayFlags::SATURDAY);
+        for (hour, minute) in [(6, 30), (7, 0), (7, 20), (7, 40), (12, 0), (18, 15)] {
+            add_journey(&mut tt, "wd1", hour, minute);
+        }
+        add_journey(&mut tt, "sat1", 10, 0);
+        tt.exceptions.insert(
+            "wd1".to_owned(),
+            ServiceException {
+                date: NaiveDate::from_ymd_opt(2024, 4, 1).unwrap(),
+                exception_type: Exception::Deleted,
+            },
+        );
+        tt.sort_journeys_and_stops();
+        tt
+    }
+
+    #[test]
+    fn stop_stats() {
+        let tt = sample_tt();
+        let time = |hour, minute| NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+        // Easter Monday is a public holiday without service, workday figures come from Tuesday.
+        let stats = tt.get_stop_stats(
+            "Village",
+            None,
+            NaiveDate::from_ymd_opt(2024, 4, 3).unwrap(),
+        );
+        assert_eq!(stats.len(), 2);
+        let workday = &stats[0];
+        assert_eq!(workday.day_type, DayType::Workday);
+        assert_eq!(workday.date, NaiveDate::from_ymd_opt(2024, 4, 2).unwrap());
+        assert_eq!(workday.departures, 6);
+        assert_eq!(workday.first_departure, time(6, 30));
+        assert_eq!(workday.last_departure, time(18, 15));
+        assert_eq!(
+            workday.departures_per_hour,
+            [(6, 1), (7, 3), (12, 1), (18, 1)].into()
+        );
+        assert_eq!(
+            workday.longest_gap,
+            Some(Gap {
+                from: time(12, 0),
+                to: time(18, 15),
+                minutes: 375
+            })
+        );
+        let saturday = &stats[1];
+        assert_eq!(saturday.day_type, DayType::Saturday);
+        assert_eq!(saturday.departures, 1);
+        assert_eq!(saturday.longest_gap, None);
+        // Buses end their journey in town.
+        let week_of = NaiveDate::from_ymd_opt(2024, 4, 3).unwrap();
+        assert!(tt.get_stop_stats("Town", None, week_of).is_empty());
+    }
+
+    #[test]
+    fn departures_after_midnight() {
+        let mut tt = sample_tt();
+        add_journey(&mut tt, "wd1", 24, 45);
+        tt.sort_journeys_and_stops();
+        let time = |hour, minute| NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+        let stats = tt.get_stop_stats(
+            "Village",
+            None,
+            NaiveDate::from_ymd_opt(2024, 4, 3).unwrap(),
+        );
+        // The last bus of a workday leaves at 00:45 the next morning.
+        let workday = &stats[0];
+        assert_eq!(workday.departures, 7);
+        assert_eq!(workday.first_departure, time(6, 30));
+        assert_eq!(workday.last_departure, time(0, 45));
+        assert_eq!(
+            workday.departures_per_hour,
+            [(6, 1), (7, 3), (12, 1), (18, 1), (24, 1)].into()
+        );
+        assert_eq!(
+            workday.longest_gap,
+            Some(Gap {
+                from: time(18, 15),
+                to: time(0, 45),
+                minutes: 390
+            })
+        );
+        // Friday's last bus doesn't count for Saturday.
+        assert_eq!(stats[1].departures, 1);
+    }
+}
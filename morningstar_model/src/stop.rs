@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// Mean earth radius used for distances between stops.
+const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+
+/// Identifier of a stop, as found in the GTFS feed it was extracted from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[serde(transparent)]
+pub struct StopId(String);
+
+impl StopId {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for StopId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::borrow::Borrow<str> for StopId {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for StopId {
+    fn from(value: &str) -> Self {
+        Self(value.to_owned())
+    }
+}
+
+impl From<String> for StopId {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+/// Stops a query is about: every quay with a name, a single quay, or the quays of a stop area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopSelector<'a> {
+    /// Stops with exactly this name, there can be one on each side of the road.
+    Name(&'a str),
+    Id(&'a StopId),
+    /// Quays whose parent station is this stop area.
+    Area(&'a StopId),
+}
+
+impl<'a> From<&'a str> for StopSelector<'a> {
+    fn from(value: &'a str) -> Self {
+        Self::Name(value)
+    }
+}
+
+impl<'a> From<&'a String> for StopSelector<'a> {
+    fn from(value: &'a String) -> Self {
+        Self::Name(value)
+    }
+}
+
+impl<'a> From<&'a StopId> for StopSelector<'a> {
+    fn from(value: &'a StopId) -> Self {
+        Self::Id(value)
+    }
+}
+
+impl Display for StopSelector<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Name(name) => write!(f, "{name}"),
+            Self::Id(stop_id) => write!(f, "stop {stop_id}"),
+            Self::Area(stop_id) => write!(f, "stop area {stop_id}"),
+        }
+    }
+}
+
+/// A stop, quay or station with its position and place in the stop hierarchy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stop {
+    pub id: StopId,
+    pub name: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Stop area grouping this stop with others, such as quays on both sides of a road.
+    pub parent_station: Option<StopId>,
+    pub location_type: LocationType,
+    pub platform_code: Option<String>,
+}
+
+impl Stop {
+    /// Great-circle distance in meters to the provided position, when this stop has one.
+    pub fn distance_to(&self, latitude: f64, longitude: f64) -> Option<f64> {
+        let (lat1, lon1) = (self.latitude?.to_radians(), self.longitude?.to_radians());
+        let (lat2, lon2) = (latitude.to_radians(), longitude.to_radians());
+        let haversine = ((lat2 - lat1) / 2.0).sin().powi(2)
+            + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+        Some(2.0 * EARTH_RADIUS_METERS * haversine.sqrt().asin())
+    }
+}
+
+/// Kind of location a stop is, mirroring GTFS `location_type`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum LocationType {
+    /// Where passengers board or alight, also called a quay.
+    #[default]
+    StopPoint,
+    /// Station or stop area grouping several stop points.
+    StopArea,
+    StationEntrance,
+    GenericNode,
+    BoardingArea,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn distance() {
+        let stop = Stop {
+            latitude: Some(48.8443),
+            longitude: Some(2.3744),
+            ..stop("gare", "Gare", None)
+        };
+        // Gare de Lyon to Gare d'Austerlitz, across the river.
+        let distance = stop.distance_to(48.8424, 2.3652).unwrap();
+        assert!((600.0..800.0).contains(&distance), "{distance}");
+        let stop = Stop {
+            latitude: None,
+            ..stop
+        };
+        assert_eq!(stop.distance_to(48.8424, 2.3652), None);
+    }
+}
@@ -0,0 +1,268 @@
+//! Reading and writing timetable files.
+//!
+//! Timetables are stored either as RON, readable but slow to load for a regional feed, or in a
+//! binary container: the `MSTT` magic, the schema version as a little endian `u16`, a flag byte
+//! telling whether the payload is zstd compressed, and the MessagePack encoded `TimeTable`.
+//! Readers tell both apart from the first bytes of the file, and upgrade timetables written
+//! with an older layout of the model.
+
+use crate::{migration, TimeTable};
+use std::io::{Read, Write};
+
+/// First bytes of a binary timetable file.
+pub const MAGIC: &[u8; 4] = b"MSTT";
+
+/// Version of the `TimeTable` layout, written in the binary header and the `schema_version`
+/// field. Bump it whenever a change to the model makes previously written files unreadable,
+/// freezing the previous layout in `migration`.
+pub const SCHEMA_VERSION: u16 = 2;
+
+/// Binary files appeared with the second layout of the model.
+const OLDEST_BINARY_VERSION: u16 = 1;
+
+const FLAG_ZSTD: u8 = 0b1;
+const HEADER_LEN: usize = MAGIC.len() + 2 + 1;
+
+/// Compression level handed to zstd, favouring size since files are written once and read
+/// often.
+const ZSTD_LEVEL: i32 = 19;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Ron,
+    Binary { compressed: bool },
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Ron(ron::Error),
+    RonDeserialisation(ron::de::SpannedError),
+    Encoding(rmp_serde::encode::Error),
+    Decoding(rmp_serde::decode::Error),
+    /// The file was written with another layout of the model than the one this program knows.
+    UnsupportedVersion {
+        found: u16,
+        supported: u16,
+    },
+    UnknownFlags(u8),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "timetable file i/o: {err}"),
+            Self::Ron(err) => write!(f, "RON timetable: {err}"),
+            Self::RonDeserialisation(err) => write!(f, "reading RON timetable: {err}"),
+            Self::Encoding(err) => write!(f, "encoding binary timetable: {err}"),
+            Self::Decoding(err) => write!(f, "decoding binary timetable: {err}"),
+            Self::UnsupportedVersion { found, supported } if found > supported => write!(
+                f,
+                "timetable schema version {found} is newer than supported version {supported}, \
+                 this program needs to be updated"
+            ),
+            Self::UnsupportedVersion { found, supported } => write!(
+                f,
+                "timetable schema version {found} is older than supported version {supported}, \
+                 the timetable needs to be extracted again"
+            ),
+            Self::UnknownFlags(flags) => write!(f, "unknown binary timetable flags {flags:#04b}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Ron(err) => Some(err),
+            Self::RonDeserialisation(err) => Some(err),
+            Self::Encoding(err) => Some(err),
+            Self::Decoding(err) => Some(err),
+            Self::UnsupportedVersion { .. } | Self::UnknownFlags(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+pub type StorageResult<T> = Result<T, Error>;
+
+/// Serialise the timetable to the writer in provided format.
+pub fn write_timetable<W: Write>(
+    timetable: &TimeTable,
+    mut writer: W,
+    format: Format,
+) -> StorageResult<()> {
+    match format {
+        Format::Ron => {
+            let serialised = ron::ser::to_string(timetable).map_err(Error::Ron)?;
+            writer.write_all(serialised.as_bytes())?;
+        }
+        Format::Binary { compressed } => {
+            let mut payload = rmp_serde::to_vec_named(timetable).map_err(Error::Encoding)?;
+            if compressed {
+                payload = zstd::stream::encode_all(payload.as_slice(), ZSTD_LEVEL)?;
+            }
+            writer.write_all(MAGIC)?;
+            writer.write_all(&SCHEMA_VERSION.to_le_bytes())?;
+            writer.write_all(&[if compressed { FLAG_ZSTD } else { 0 }])?;
+            writer.write_all(&payload)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Deserialise a timetable from the reader, detecting whether it is RON or binary.
+pub fn read_timetable<R: Read>(mut reader: R) -> StorageResult<TimeTable> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    if bytes.starts_with(MAGIC) {
+        read_binary(&bytes)
+    } else {
+        read_ron(&bytes)
+    }
+}
+
+fn read_ron(bytes: &[u8]) -> StorageResult<TimeTable> {
+    let mut deserializer =
+        ron::Deserializer::from_bytes(bytes).map_err(Error::RonDeserialisation)?;
+    let newer_version = std::cell::Cell::new(None);
+    let timetable = migration::deserialize_ron(&mut deserializer, &newer_version)
+        .and_then(|timetable| deserializer.end().map(|()| timetable));
+    if let Some(found) = newer_version.get() {
+        return Err(Error::UnsupportedVersion {
+            found,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    timetable.map_err(|err| Error::RonDeserialisation(deserializer.span_error(err)))
+}
+
+fn check_version(version: u16) -> StorageResult<()> {
+    if version > SCHEMA_VERSION {
+        Err(Error::UnsupportedVersion {
+            found: version,
+            supported: SCHEMA_VERSION,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+fn read_binary(bytes: &[u8]) -> StorageResult<TimeTable> {
+    let header = bytes.get(..HEADER_LEN).ok_or_else(|| {
+        Error::Io(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "truncated binary timetable header",
+        ))
+    })?;
+    let version = u16::from_le_bytes([header[4], header[5]]);
+    let flags = header[6];
+    check_version(version)?;
+    if version < OLDEST_BINARY_VERSION {
+        return Err(Error::UnsupportedVersion {
+            found: version,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    if flags & !FLAG_ZSTD != 0 {
+        return Err(Error::UnknownFlags(flags));
+    }
+    let payload = &bytes[HEADER_LEN..];
+    if flags & FLAG_ZSTD != 0 {
+        let decompressed = zstd::stream::decode_all(payload)?;
+        read_msgpack(version, &decompressed)
+    } else {
+        read_msgpack(version, payload)
+    }
+}
+
+fn read_msgpack(version: u16, payload: &[u8]) -> StorageResult<TimeTable> {
+    let mut deserializer = rmp_serde::Deserializer::from_read_ref(payload);
+    migration::deserialize_version(version, &mut deserializer).map_err(Error::Decoding)
+}
+
+impl TimeTable {
+    /// Load a timetable file, whichever format it was saved in.
+    pub fn load(path: impl AsRef<std::path::Path>) -> StorageResult<Self> {
+        let file = std::fs::File::open(path)?;
+        read_timetable(std::io::BufReader::new(file))
+    }
+
+    pub fn save(&self, path: impl AsRef<std::path::Path>, format: Format) -> StorageResult<()> {
+        let file = std::fs::File::create(path)?;
+        write_timetable(self, std::io::BufWriter::new(file), format)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+
+    fn sample_tt() -> TimeTable {
+        let mut tt = TimeTable::new();
+        tt.timezone = "Europe/Paris".to_owned();
+        tt.extracted_from = "gtfs.zip".to_owned();
+        tt.extracted_route_ids = vec!["r1".to_owned()];
+        tt.journeys.push(crate::Journey {
+            stops: vec![crate::StopTime {
+                arrival_time: crate::ServiceTime::from_hms(24, 10, 0),
+                ..stop_time(24, 12, "gare")
+            }],
+            ..trip("t1", "wd1", &[])
+        });
+        tt
+    }
+
+    fn roundtrip(format: Format) -> TimeTable {
+        let mut bytes = vec![];
+        write_timetable(&sample_tt(), &mut bytes, format).unwrap();
+        assert_eq!(bytes.starts_with(MAGIC), format != Format::Ron);
+        read_timetable(bytes.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn ron_and_binary_roundtrip() {
+        for format in [
+            Format::Ron,
+            Format::Binary { compressed: false },
+            Format::Binary { compressed: true },
+        ] {
+            let tt = roundtrip(format);
+            assert_eq!(tt.timezone, "Europe/Paris");
+            assert_eq!(tt.extracted_route_ids, ["r1"]);
+            assert_eq!(tt.journeys.len(), 1);
+            assert_eq!(
+                tt.journeys[0].stops[0].departure_time,
+                crate::ServiceTime::from_hms(24, 12, 0)
+            );
+        }
+    }
+
+    #[test]
+    fn other_schema_version() {
+        let mut bytes = vec![];
+        write_timetable(
+            &sample_tt(),
+            &mut bytes,
+            Format::Binary { compressed: false },
+        )
+        .unwrap();
+        bytes[4..6].copy_from_slice(&(SCHEMA_VERSION + 1).to_le_bytes());
+        let Err(err) = read_timetable(bytes.as_slice()) else {
+            panic!("a timetable from another schema version was read");
+        };
+        assert!(matches!(
+            err,
+            Error::UnsupportedVersion { found, supported: SCHEMA_VERSION } if found == SCHEMA_VERSION + 1
+        ));
+        assert!(err.to_string().contains("needs to be updated"));
+    }
+}
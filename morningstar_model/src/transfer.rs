@@ -0,0 +1,36 @@
+use crate::StopId;
+use serde::{Deserialize, Serialize};
+
+/// Rule for changing from a journey calling at a stop to one calling at another, or the same,
+/// stop, mirroring a GTFS `transfers.txt` entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transfer {
+    pub from_stop_id: StopId,
+    pub to_stop_id: StopId,
+    pub kind: TransferKind,
+    /// Seconds needed to make the transfer.
+    pub min_transfer_time: Option<u32>,
+}
+
+impl Transfer {
+    /// Seconds to allow between arriving and leaving again, `None` when the transfer can't be
+    /// made at all.
+    pub fn duration(&self, default_secs: u32) -> Option<u32> {
+        match self.kind {
+            TransferKind::NotPossible => None,
+            // The departing vehicle waits for the arriving one.
+            TransferKind::Timed => Some(0),
+            TransferKind::Recommended | TransferKind::MinimumTime => {
+                Some(self.min_transfer_time.unwrap_or(default_secs))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TransferKind {
+    Recommended,
+    Timed,
+    MinimumTime,
+    NotPossible,
+}
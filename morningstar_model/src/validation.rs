@@ -0,0 +1,292 @@
+//! Checks on a timetable's data, which loading accepts as it comes.
+//!
+//! Findings are errors when queries would panic or return wrong times, warnings when some data is
+//! only useless or suspicious. Timetables with errors shouldn't replace one in use.
+
+use crate::{StopId, TimeTable};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Something wrong with the timetable, and where.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Finding {
+    /// There is nothing to query.
+    NoJourney,
+    /// The timezone isn't a known one, times would be read as UTC.
+    UnknownTimezone(String),
+    /// No timezone is given, times are read as UTC.
+    MissingTimezone,
+    /// Journey without any stop, it has neither a first departure nor a destination.
+    EmptyJourney { trip_id: String },
+    /// The bus leaves the stop at `position` before arriving at it, or arrives there before
+    /// leaving the previous stop.
+    TimeGoesBackwards {
+        trip_id: String,
+        position: usize,
+        stop_id: StopId,
+    },
+    /// Journey with a single stop, nobody can travel on it.
+    SingleStop { trip_id: String },
+    /// Journey sharing its trip id with an earlier one.
+    DuplicateTrip { trip_id: String },
+    /// The journey's service has neither a pattern nor exceptions, the journey never runs.
+    UnknownService { trip_id: String, service_id: String },
+    /// The journey calls at a stop missing from the stop table, which has no name.
+    UnknownStop { trip_id: String, stop_id: StopId },
+    /// The journey's route is missing from the route table, it has no line name.
+    UnknownRoute { trip_id: String, route_id: String },
+}
+
+impl Finding {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::NoJourney
+            | Self::UnknownTimezone(_)
+            | Self::EmptyJourney { .. }
+            | Self::TimeGoesBackwards { .. } => Severity::Error,
+            Self::MissingTimezone
+            | Self::SingleStop { .. }
+            | Self::DuplicateTrip { .. }
+            | Self::UnknownService { .. }
+            | Self::UnknownStop { .. }
+            | Self::UnknownRoute { .. } => Severity::Warning,
+        }
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoJourney => write!(f, "no journey"),
+            Self::UnknownTimezone(timezone) => write!(f, "unknown timezone {timezone}"),
+            Self::MissingTimezone => write!(f, "no timezone, times are read as UTC"),
+            Self::EmptyJourney { trip_id } => write!(f, "trip {trip_id} has no stop"),
+            Self::TimeGoesBackwards {
+                trip_id,
+                position,
+                stop_id,
+            } => write!(
+                f,
+                "trip {trip_id} goes back in time at stop {stop_id}, position {position}"
+            ),
+            Self::SingleStop { trip_id } => write!(f, "trip {trip_id} has a single stop"),
+            Self::DuplicateTrip { trip_id } => write!(f, "trip {trip_id} appears more than once"),
+            Self::UnknownService {
+                trip_id,
+                service_id,
+            } => write!(
+                f,
+                "trip {trip_id} never runs, service {service_id} has no calendar"
+            ),
+            Self::UnknownStop { trip_id, stop_id } => {
+                write!(f, "trip {trip_id} calls at unknown stop {stop_id}")
+            }
+            Self::UnknownRoute { trip_id, route_id } => {
+                write!(f, "trip {trip_id} belongs to unknown route {route_id}")
+            }
+        }
+    }
+}
+
+/// Findings of `TimeTable::validate`, in the order of the timetable's journeys.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Validation {
+    pub findings: Vec<Finding>,
+}
+
+impl Validation {
+    pub fn is_empty(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// Whether the timetable shouldn't be used.
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Finding> {
+        self.findings
+            .iter()
+            .filter(|finding| finding.severity() == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Finding> {
+        self.findings
+            .iter()
+            .filter(|finding| finding.severity() == Severity::Warning)
+    }
+}
+
+impl Display for Validation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for finding in &self.findings {
+            writeln!(f, "{}: {finding}", finding.severity())?;
+        }
+        Ok(())
+    }
+}
+
+impl TimeTable {
+    /// Checks journeys against the rest of the timetable. Stop times are checked in the order
+    /// they are stored, so this is best run before sorting them: sorted stop times never go back
+    /// in time, even when they were written that way.
+    pub fn validate(&self) -> Validation {
+        let mut findings = vec![];
+        if self.journeys.is_empty() {
+            findings.push(Finding::NoJourney);
+        }
+        if self.timezone.is_empty() {
+            findings.push(Finding::MissingTimezone);
+        } else if self.timezone.parse::<chrono_tz::Tz>().is_err() {
+            findings.push(Finding::UnknownTimezone(self.timezone.clone()));
+        }
+        let mut trip_ids = HashSet::new();
+        for journey in &self.journeys {
+            let trip_id = || journey.trip_id.clone();
+            if !trip_ids.insert(journey.trip_id.as_str()) {
+                findings.push(Finding::DuplicateTrip { trip_id: trip_id() });
+            }
+            match journey.stops.len() {
+                0 => findings.push(Finding::EmptyJourney { trip_id: trip_id() }),
+                1 => findings.push(Finding::SingleStop { trip_id: trip_id() }),
+                _ => {}
+            }
+            let mut previous_departure = None;
+            for (position, stop) in journey.stops.iter().enumerate() {
+                if stop.departure_time < stop.arrival_time
+                    || previous_departure.is_some_and(|departure| stop.arrival_time < departure)
+                {
+                    findings.push(Finding::TimeGoesBackwards {
+                        trip_id: trip_id(),
+                        position,
+                        stop_id: stop.stop_id.clone(),
+                    });
+                }
+                previous_departure = Some(stop.departure_time);
+            }
+            if !self.service_patterns.contains_key(&journey.service_id)
+                && !self.exceptions.contains_key(&journey.service_id)
+            {
+                findings.push(Finding::UnknownService {
+                    trip_id: trip_id(),
+                    service_id: journey.service_id.clone(),
+                });
+            }
+            let mut unknown_stops = HashSet::new();
+            for stop in &journey.stops {
+                if !self.stops.contains_key(&stop.stop_id) && unknown_stops.insert(&stop.stop_id) {
+                    findings.push(Finding::UnknownStop {
+                        trip_id: trip_id(),
+                        stop_id: stop.stop_id.clone(),
+                    });
+                }
+            }
+            if !self.routes.contains_key(&journey.route_id) {
+                findings.push(Finding::UnknownRoute {
+                    trip_id: trip_id(),
+                    route_id: journey.route_id.clone(),
+                });
+            }
+        }
+        Validation { findings }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+    use crate::*;
+
+    /// Journey calling at the church at each `(minute, wait)` past eight.
+    fn journey(trip_id: &str, service_id: &str, times: &[(u32, u32)]) -> Journey {
+        Journey {
+            stops: times
+                .iter()
+                .map(|(minute, wait)| StopTime {
+                    departure_time: ServiceTime::from_hms(8, minute + wait, 0),
+                    ..stop_time(8, *minute, "eglise")
+                })
+                .collect(),
+            ..trip(trip_id, service_id, &[])
+        }
+    }
+
+    fn sample_tt() -> TimeTable {
+        let mut tt = TimeTable::new();
+        tt.timezone = "Europe/Paris".to_owned();
+        tt.routes.insert(
+            "r1".to_owned(),
+            Route {
+                id: "r1".to_owned(),
+                short_name: Some("2245".to_owned()),
+                long_name: None,
+                color: None,
+                text_color: None,
+                agency_id: None,
+                agency_name: None,
+            },
+        );
+        add_stops(&mut tt, &[("eglise", "Église")]);
+        add_service(&mut tt, "wd1", WeekdayFlags::WORKDAYS);
+        tt.journeys.push(journey("t1", "wd1", &[(0, 0), (10, 1)]));
+        tt
+    }
+
+    #[test]
+    fn clean() {
+        let tt = sample_tt();
+        assert!(tt.validate().is_empty());
+    }
+
+    #[test]
+    fn findings() {
+        let mut tt = sample_tt();
+        tt.journeys.push(journey("t1", "wd1", &[(0, 0), (10, 0)]));
+        tt.journeys.push(journey("t2", "wd1", &[]));
+        tt.journeys.push(journey("t3", "wd1", &[(10, 0), (5, 0)]));
+        tt.journeys
+            .push(journey("t4", "sundays", &[(0, 0), (10, 0)]));
+        let validation = tt.validate();
+        assert_eq!(
+            validation.findings,
+            [
+                Finding::DuplicateTrip {
+                    trip_id: "t1".to_owned()
+                },
+                Finding::EmptyJourney {
+                    trip_id: "t2".to_owned()
+                },
+                Finding::TimeGoesBackwards {
+                    trip_id: "t3".to_owned(),
+                    position: 1,
+                    stop_id: "eglise".into()
+                },
+                Finding::UnknownService {
+                    trip_id: "t4".to_owned(),
+                    service_id: "sundays".to_owned()
+                },
+            ]
+        );
+        assert!(validation.has_errors());
+        assert_eq!(validation.errors().count(), 2);
+        // Sorting doesn't choke on the empty journey.
+        tt.sort_journeys_and_stops();
+    }
+}
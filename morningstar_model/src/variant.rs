@@ -0,0 +1,278 @@
+//! Variants of a route: journeys grouped by the sequence of stops they call at, each described by
+//! how it differs from the route's main pattern.
+//!
+//! The main pattern of a route and direction is the one most journeys follow. Other patterns are
+//! compared to it stop name by stop name, aligned on their longest common subsequence. Patterns
+//! sharing less than half their stops with every main pattern are main patterns of their own,
+//! which is what happens to the way back when the feed doesn't give journey directions.
+
+use crate::{Direction, StopId, TimeTable};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// Label of main patterns.
+pub const MAIN_VARIANT_LABEL: &str = "main route";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Variant {
+    pub route_id: String,
+    pub direction_id: Option<Direction>,
+    /// Stops called at, in order.
+    pub stop_ids: Vec<StopId>,
+    /// Number of journeys following this pattern.
+    pub journey_count: usize,
+    /// Whether this is a main pattern, or calls at the same stops as one on different quays.
+    pub main: bool,
+    /// Where this pattern differs from its main pattern, empty for main patterns.
+    pub differences: Vec<Difference>,
+    /// Short description such as "via Potato Factory" or "short turn at Marché".
+    pub label: String,
+}
+
+/// Stop where a pattern differs from its main pattern, by name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Difference {
+    /// Calls at a stop the main pattern doesn't.
+    Via(String),
+    /// Doesn't call at a stop the main pattern calls at, between its own first and last stops.
+    Skips(String),
+    /// Starts along the main pattern rather than at its first stop.
+    StartsAt(String),
+    /// Ends along the main pattern rather than at its last stop.
+    ShortTurn(String),
+    /// Starts from a stop before the main pattern's first one.
+    ExtendedFrom(String),
+    /// Carries on past the main pattern's last stop.
+    ExtendedTo(String),
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Via(stop) => write!(f, "via {stop}"),
+            Self::Skips(stop) => write!(f, "skips {stop}"),
+            Self::StartsAt(stop) => write!(f, "starts at {stop}"),
+            Self::ShortTurn(stop) => write!(f, "short turn at {stop}"),
+            Self::ExtendedFrom(stop) => write!(f, "from {stop}"),
+            Self::ExtendedTo(stop) => write!(f, "extended to {stop}"),
+        }
+    }
+}
+
+impl TimeTable {
+    /// Stop patterns of the timetable's journeys, main patterns first.
+    pub fn get_variants(&self) -> &[Variant] {
+        self.index().variants()
+    }
+
+    /// Pattern the journey at provided index in `journeys` follows.
+    pub fn get_journey_variant(&self, journey_idx: usize) -> Option<&Variant> {
+        self.index().journey_variant(journey_idx)
+    }
+}
+
+/// Variants of the timetable, and the variant of each journey by index.
+pub(crate) fn detect(tt: &TimeTable) -> (Vec<Variant>, Vec<usize>) {
+    type Pattern<'a> = (&'a str, Option<Direction>, Vec<&'a StopId>);
+    let mut journeys_by_pattern: HashMap<Pattern, Vec<usize>> = HashMap::new();
+    for (journey_idx, journey) in tt.journeys.iter().enumerate() {
+        let stop_ids = journey.stops.iter().map(|stop| &stop.stop_id).collect();
+        journeys_by_pattern
+            .entry((journey.route_id.as_str(), journey.direction_id, stop_ids))
+            .or_default()
+            .push(journey_idx);
+    }
+    let mut patterns: Vec<_> = journeys_by_pattern.into_iter().collect();
+    // Most followed first so that main patterns come before their variants, then in a stable
+    // order for labels not to change from one run to the next.
+    patterns.sort_by(|(a, a_journeys), (b, b_journeys)| {
+        b_journeys
+            .len()
+            .cmp(&a_journeys.len())
+            .then(b.2.len().cmp(&a.2.len()))
+            .then_with(|| a.cmp(b))
+    });
+
+    let mut variants: Vec<Variant> = vec![];
+    let mut main_names: Vec<(usize, Vec<&str>)> = vec![];
+    let mut journey_variants = vec![0; tt.journeys.len()];
+    for ((route_id, direction_id, stop_ids), journeys) in patterns {
+        let names: Vec<_> = stop_ids.iter().map(|id| tt.get_stop_name(id)).collect();
+        let closest_main = main_names
+            .iter()
+            .filter(|(variant_idx, _)| {
+                let main = &variants[*variant_idx];
+                main.route_id == route_id && main.direction_id == direction_id
+            })
+            .map(|(_, main)| (main, common_subsequence(&names, main)))
+            .max_by_key(|(_, common)| common.len())
+            .filter(|(_, common)| common.len() * 2 >= names.len() && !common.is_empty());
+        let differences = match closest_main {
+            Some((main, common)) => differences(&names, main, &common),
+            None => {
+                main_names.push((variants.len(), names.clone()));
+                vec![]
+            }
+        };
+        let label = if differences.is_empty() {
+            MAIN_VARIANT_LABEL.to_owned()
+        } else {
+            differences
+                .iter()
+                .map(Difference::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        for journey_idx in &journeys {
+            journey_variants[*journey_idx] = variants.len();
+        }
+        variants.push(Variant {
+            route_id: route_id.to_owned(),
+            direction_id,
+            stop_ids: stop_ids.into_iter().cloned().collect(),
+            journey_count: journeys.len(),
+            main: differences.is_empty(),
+            differences,
+            label,
+        });
+    }
+    (variants, journey_variants)
+}
+
+/// Positions in `a` and `b` of a longest common subsequence of both, in order.
+fn common_subsequence(a: &[&str], b: &[&str]) -> Vec<(usize, usize)> {
+    // lengths[i][j] is the length of the longest common subsequence of a[i..] and b[j..].
+    let mut lengths = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i] == b[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j, mut common) = (0, 0, vec![]);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            common.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    common
+}
+
+/// Differences of a pattern from its main pattern, given stop positions they have in common.
+fn differences(names: &[&str], main: &[&str], common: &[(usize, usize)]) -> Vec<Difference> {
+    let (Some(&(first, main_first)), Some(&(last, main_last))) = (common.first(), common.last())
+    else {
+        return vec![];
+    };
+    let mut differences = vec![];
+    if first > 0 {
+        differences.push(Difference::ExtendedFrom(names[0].to_owned()));
+    } else if main_first > 0 {
+        differences.push(Difference::StartsAt(names[0].to_owned()));
+    }
+    // Walks both patterns between their first and last common stops.
+    for window in common.windows(2) {
+        let [(i, j), (next_i, next_j)] = [window[0], window[1]];
+        for name in &names[i + 1..next_i] {
+            differences.push(Difference::Via((*name).to_owned()));
+        }
+        for name in &main[j + 1..next_j] {
+            differences.push(Difference::Skips((*name).to_owned()));
+        }
+    }
+    for name in names.iter().take(first).skip(1) {
+        differences.push(Difference::Via((*name).to_owned()));
+    }
+    let last_position = names.len() - 1;
+    for name in names.iter().take(last_position).skip(last + 1) {
+        differences.push(Difference::Via((*name).to_owned()));
+    }
+    if last < last_position {
+        differences.push(Difference::ExtendedTo(names[last_position].to_owned()));
+    } else if main_last + 1 < main.len() {
+        differences.push(Difference::ShortTurn(names[last_position].to_owned()));
+    }
+    differences
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::*;
+    use crate::*;
+
+    fn add_journey(tt: &mut TimeTable, stop_ids: &[&str]) {
+        let stops: Vec<_> = stop_ids
+            .iter()
+            .enumerate()
+            .map(|(position, stop_id)| (8, position as u32 * 5, *stop_id))
+            .collect();
+        tt.journeys
+            .push(trip(&format!("t{}", tt.journeys.len()), "wd1", &stops));
+    }
+
+    fn sample_tt() -> TimeTable {
+        let mut tt = TimeTable::new();
+        add_stops(
+            &mut tt,
+            &[
+                ("eglise", "Église"),
+                ("marche", "Marché"),
+                ("marche2", "Marché"),
+                ("potato", "Potato Factory"),
+                ("mairie", "Mairie"),
+                ("gare", "Gare"),
+            ],
+        );
+        for _ in 0..3 {
+            add_journey(&mut tt, &["eglise", "marche", "mairie", "gare"]);
+        }
+        add_journey(&mut tt, &["eglise", "marche", "potato", "mairie", "gare"]);
+        add_journey(&mut tt, &["eglise", "marche"]);
+        add_journey(&mut tt, &["eglise", "marche2", "gare"]);
+        add_journey(&mut tt, &["eglise", "marche2", "mairie", "gare"]);
+        add_journey(&mut tt, &["gare", "mairie", "marche", "eglise"]);
+        tt
+    }
+
+    #[test]
+    fn labels() {
+        let tt = sample_tt();
+        let label = |journey_idx| tt.get_journey_variant(journey_idx).unwrap().label.as_str();
+        assert_eq!(label(0), MAIN_VARIANT_LABEL);
+        assert!(tt.get_journey_variant(0).unwrap().main);
+        assert_eq!(label(3), "via Potato Factory");
+        assert_eq!(label(4), "short turn at Marché");
+        assert_eq!(label(5), "skips Mairie");
+        // Other quay, same stops.
+        assert_eq!(label(6), MAIN_VARIANT_LABEL);
+        // The way back has a main pattern of its own.
+        assert_eq!(label(7), MAIN_VARIANT_LABEL);
+        assert_eq!(tt.get_variants().len(), 6);
+        assert_eq!(tt.get_variants()[0].journey_count, 3);
+    }
+
+    #[test]
+    fn extensions() {
+        let main = ["Église", "Marché", "Gare"];
+        let names = ["Mairie", "Marché", "Gare", "Port"];
+        let common = common_subsequence(&names, &main);
+        assert_eq!(
+            differences(&names, &main, &common),
+            [
+                Difference::ExtendedFrom("Mairie".to_owned()),
+                Difference::ExtendedTo("Port".to_owned())
+            ]
+        );
+    }
+}
@@ -1,24 +1,32 @@
 pub trait GtfsExtract {
-    fn extract_gtfs_route(
+    fn extract_gtfs_routes(
         &mut self,
         gtfs: gtfs_structures::Gtfs,
-        route_id: &str,
+        route_ids: &[String],
     ) -> Result<(), Box<dyn std::error::Error>>;
 }
 
 impl GtfsExtract for morningstar_model::TimeTable {
-    fn extract_gtfs_route(
+    fn extract_gtfs_routes(
         &mut self,
         gtfs: gtfs_structures::Gtfs,
-        route_id: &str,
+        route_ids: &[String],
     ) -> Result<(), Box<dyn std::error::Error>> {
         self.timezone = gtfs.agencies[0].timezone.clone();
+        for route_id in route_ids {
+            let route = gtfs
+                .routes
+                .get(route_id)
+                .ok_or_else(|| format!("route {route_id} doesn't exist"))?;
+            self.routes
+                .insert(route_id.clone(), route_convert(route, &gtfs.agencies));
+        }
         let journeys: Vec<_> = gtfs
             .trips
             .iter()
-            .filter(|(_, candidate_trip)| candidate_trip.route_id == route_id)
+            .filter(|(_, candidate_trip)| route_ids.contains(&candidate_trip.route_id))
             .map(|(_, value)| value)
-            .filter_map(trip_convert)
+            .flat_map(trip_journeys)
             .collect();
         if journeys.is_empty() {
             return Err("no trip was available".into());
@@ -27,8 +35,12 @@ impl GtfsExtract for morningstar_model::TimeTable {
             .iter()
             .map(|journey| journey.service_id.clone())
             .for_each(|service_id| extract_pattern_and_exceptions(self, &gtfs, service_id));
+        journeys
+            .iter()
+            .flat_map(|journey| &journey.stops)
+            .for_each(|stop_time| extract_stop(self, &gtfs, stop_time.stop_id.as_str()));
+        extract_transfers(self, &gtfs);
         self.journeys = journeys;
-        self.sort_journeys_and_stops();
         Ok(())
     }
 }
@@ -44,18 +56,97 @@ fn extract_pattern_and_exceptions(
     }
     if let Some(callendar_dates) = gtfs.calendar_dates.get(&service_id) {
         for callendar_date in callendar_dates {
-            let excpetion = morningstar_model::ServiceException {
+            let exception = morningstar_model::ServiceException {
                 date: callendar_date.date,
                 exception_type: match callendar_date.exception_type {
                     gtfs_structures::Exception::Added => morningstar_model::Exception::Added,
                     gtfs_structures::Exception::Deleted => morningstar_model::Exception::Deleted,
                 },
             };
-            tt.excpetions.insert(service_id.clone(), excpetion);
+            tt.exceptions.insert(service_id.clone(), exception);
         }
     }
 }
 
+/// Adds the stop to the timetable along with its parent stations.
+fn extract_stop(
+    tt: &mut morningstar_model::TimeTable,
+    gtfs: &gtfs_structures::Gtfs,
+    stop_id: &str,
+) {
+    if tt.stops.contains_key(stop_id) {
+        return;
+    }
+    let Some(stop) = gtfs.stops.get(stop_id) else {
+        return;
+    };
+    let stop = stop_convert(stop);
+    let parent_station = stop.parent_station.clone();
+    tt.stops.insert(stop.id.clone(), stop);
+    if let Some(parent_station) = parent_station {
+        extract_stop(tt, gtfs, parent_station.as_str());
+    }
+}
+
+/// Adds the transfers between extracted stops.
+fn extract_transfers(tt: &mut morningstar_model::TimeTable, gtfs: &gtfs_structures::Gtfs) {
+    let transfers: Vec<_> = tt
+        .stops
+        .keys()
+        .filter_map(|stop_id| gtfs.stops.get(stop_id.as_str()))
+        .flat_map(|stop| {
+            stop.transfers
+                .iter()
+                .filter(|transfer| tt.stops.contains_key(transfer.to_stop_id.as_str()))
+                .filter_map(|transfer| transfer_convert(&stop.id, transfer))
+        })
+        .collect();
+    tt.transfers = transfers;
+}
+
+/// In-seat transfer rules are left out, they are about trips rather than stops.
+fn transfer_convert(
+    from_stop_id: &str,
+    transfer: &gtfs_structures::StopTransfer,
+) -> Option<morningstar_model::Transfer> {
+    use gtfs_structures::TransferType;
+    use morningstar_model::TransferKind;
+    let kind = match transfer.transfer_type {
+        TransferType::Recommended => TransferKind::Recommended,
+        TransferType::Timed => TransferKind::Timed,
+        TransferType::MinTime => TransferKind::MinimumTime,
+        TransferType::Impossible => TransferKind::NotPossible,
+        TransferType::StayOnBoard | TransferType::MustAlight => return None,
+    };
+    Some(morningstar_model::Transfer {
+        from_stop_id: from_stop_id.into(),
+        to_stop_id: transfer.to_stop_id.as_str().into(),
+        kind,
+        min_transfer_time: transfer.min_transfer_time,
+    })
+}
+
+fn stop_convert(stop: &gtfs_structures::Stop) -> morningstar_model::Stop {
+    use gtfs_structures::LocationType;
+    morningstar_model::Stop {
+        id: stop.id.as_str().into(),
+        name: stop.name.clone().unwrap_or_default(),
+        latitude: stop.latitude,
+        longitude: stop.longitude,
+        parent_station: stop.parent_station.as_deref().map(Into::into),
+        location_type: match stop.location_type {
+            LocationType::StopArea => morningstar_model::LocationType::StopArea,
+            LocationType::StationEntrance => morningstar_model::LocationType::StationEntrance,
+            LocationType::GenericNode => morningstar_model::LocationType::GenericNode,
+            LocationType::BoardingArea => morningstar_model::LocationType::BoardingArea,
+            LocationType::StopPoint | LocationType::Unknown(_) => {
+                morningstar_model::LocationType::StopPoint
+            }
+        },
+        platform_code: stop.platform_code.clone(),
+    }
+}
+
 fn callendar_to_pattern(calendar: &gtfs_structures::Calendar) -> morningstar_model::ServicePattern {
     use morningstar_model::WeekdayFlags;
     let mut pattern = morningstar_model::ServicePattern {
@@ -87,6 +178,65 @@ fn callendar_to_pattern(calendar: &gtfs_structures::Calendar) -> morningstar_mod
     pattern
 }
 
+fn route_convert(
+    route: &gtfs_structures::Route,
+    agencies: &[gtfs_structures::Agency],
+) -> morningstar_model::Route {
+    let agency = match &route.agency_id {
+        Some(agency_id) => agencies
+            .iter()
+            .find(|agency| agency.id.as_ref() == Some(agency_id)),
+        None => agencies.first(),
+    };
+    morningstar_model::Route {
+        id: route.id.clone(),
+        short_name: route.short_name.clone(),
+        long_name: route.long_name.clone(),
+        color: color_convert(route.color, ROUTE_COLOR_DEFAULT),
+        text_color: color_convert(route.text_color, ROUTE_TEXT_COLOR_DEFAULT),
+        agency_id: route.agency_id.clone(),
+        agency_name: agency.map(|agency| agency.name.clone()),
+    }
+}
+
+/// Colour gtfs_structures fills in when a route has none.
+const ROUTE_COLOR_DEFAULT: (u8, u8, u8) = (255, 255, 255);
+/// Text colour gtfs_structures fills in when a route has none.
+const ROUTE_TEXT_COLOR_DEFAULT: (u8, u8, u8) = (0, 0, 0);
+
+/// Colour as hex, `None` when it is the default filled in for feeds that leave it out, which
+/// can't be told apart from one the feed gives.
+fn color_convert(color: impl Into<(u8, u8, u8)>, default: (u8, u8, u8)) -> Option<String> {
+    let (r, g, b) = color.into();
+    ((r, g, b) != default).then(|| format!("{r:02X}{g:02X}{b:02X}"))
+}
+
+/// Journeys of the trip: the trip itself, or one journey per departure when it is headway-based.
+fn trip_journeys(trip: &gtfs_structures::Trip) -> Vec<morningstar_model::Journey> {
+    let Some(journey) = trip_convert(trip) else {
+        return vec![];
+    };
+    if trip.frequencies.is_empty() {
+        return vec![journey];
+    }
+    trip.frequencies
+        .iter()
+        .map(frequency_convert)
+        .flat_map(|frequency| journey.expand_frequency(frequency).collect::<Vec<_>>())
+        .collect()
+}
+
+fn frequency_convert(frequency: &gtfs_structures::Frequency) -> morningstar_model::Frequency {
+    use morningstar_model::ServiceTime;
+    morningstar_model::Frequency {
+        start_time: ServiceTime::from_seconds(frequency.start_time),
+        end_time: ServiceTime::from_seconds(frequency.end_time),
+        headway_secs: frequency.headway_secs,
+        // Left out, GTFS considers trips to be frequency-based.
+        exact_times: frequency.exact_times == Some(gtfs_structures::ExactTimes::ScheduleBased),
+    }
+}
+
 fn trip_convert(trip: &gtfs_structures::Trip) -> Option<morningstar_model::Journey> {
     let stops: Vec<_> = trip
         .stop_times
@@ -98,21 +248,47 @@ fn trip_convert(trip: &gtfs_structures::Trip) -> Option<morningstar_model::Journ
         None
     } else {
         Some(morningstar_model::Journey {
+            trip_id: trip.id.clone(),
+            route_id: trip.route_id.clone(),
             service_id: trip.service_id.clone(),
+            trip_headsign: trip.trip_headsign.clone(),
+            trip_short_name: trip.trip_short_name.clone(),
+            direction_id: trip.direction_id.map(|direction| match direction {
+                gtfs_structures::DirectionType::Outbound => morningstar_model::Direction::Outbound,
+                gtfs_structures::DirectionType::Inbound => morningstar_model::Direction::Inbound,
+            }),
+            block_id: trip.block_id.clone(),
             stops,
+            frequency: None,
         })
     }
 }
 
 fn stop_time_convert(stop_time: &gtfs_structures::StopTime) -> Option<morningstar_model::StopTime> {
-    let stop_name = stop_time.stop.name.clone()?;
-    let stop_id = stop_time.stop.id.clone();
-    let seconds_from_midnight = stop_time.arrival_time.or(stop_time.departure_time)?;
-    let time_of_day =
-        chrono::NaiveTime::from_num_seconds_from_midnight_opt(seconds_from_midnight, 0)?;
+    use morningstar_model::ServiceTime;
+    let stop_id = stop_time.stop.id.as_str().into();
+    // GTFS times count from the start of the service day and go past 24:00:00 for trips that
+    // run after midnight. Either can be left out when the bus doesn't wait at the stop.
+    let arrival_time = stop_time.arrival_time.or(stop_time.departure_time)?;
+    let departure_time = stop_time.departure_time.or(stop_time.arrival_time)?;
     Some(morningstar_model::StopTime {
-        time: time_of_day,
-        stop_name: stop_name,
+        arrival_time: ServiceTime::from_seconds(arrival_time),
+        departure_time: ServiceTime::from_seconds(departure_time),
         stop_id,
+        pickup_type: pickup_drop_off_convert(stop_time.pickup_type),
+        drop_off_type: pickup_drop_off_convert(stop_time.drop_off_type),
     })
 }
+
+fn pickup_drop_off_convert(
+    pickup_drop_off: gtfs_structures::PickupDropOffType,
+) -> morningstar_model::PickupDropOff {
+    use gtfs_structures::PickupDropOffType;
+    use morningstar_model::PickupDropOff;
+    match pickup_drop_off {
+        PickupDropOffType::NotAvailable => PickupDropOff::NotAvailable,
+        PickupDropOffType::ArrangeByPhone => PickupDropOff::ArrangeByPhone,
+        PickupDropOffType::CoordinateWithDriver => PickupDropOff::CoordinateWithDriver,
+        PickupDropOffType::Regular | PickupDropOffType::Unknown(_) => PickupDropOff::Regular,
+    }
+}
@@ -2,20 +2,131 @@ mod extractor;
 use chrono::prelude::*;
 
 use clap::Parser;
+
+/// Either extracts routes from a GTFS feed, or runs one of the subcommands.
+#[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub opt: Option<Opt>,
+}
+
+#[derive(clap::Subcommand)]
+pub enum Command {
+    /// Tells what changed from one timetable to a newer one.
+    Diff {
+        old: std::path::PathBuf,
+        new: std::path::PathBuf,
+
+        /// Output as JSON rather than text.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Merges timetables extracted from different feeds into one. Route, trip, service and stop
+    /// ids of each are prefixed with its file name, without extension.
+    Merge {
+        #[arg(required = true, num_args = 2..)]
+        timetables: Vec<std::path::PathBuf>,
+
+        #[arg(short = 'o')]
+        out: std::path::PathBuf,
+
+        /// Links stops of different timetables with the same name less than this many meters
+        /// apart.
+        #[arg(long)]
+        same_name_within: Option<f64>,
+
+        /// Links two stops given by prefixed id as `KEPT=LINKED`, calls at the second one
+        /// becoming calls at the first one.
+        #[arg(long, value_parser = parse_link)]
+        link: Vec<(String, String)>,
+
+        /// Format of the timetable file, binary loads much faster.
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Ron)]
+        format: OutputFormat,
+
+        /// Compress binary timetables with zstd.
+        #[arg(long)]
+        zstd: bool,
+    },
+}
+
+fn parse_link(link: &str) -> Result<(String, String), String> {
+    link.split_once('=')
+        .map(|(kept, linked)| (kept.to_owned(), linked.to_owned()))
+        .ok_or_else(|| format!("{link} isn't formatted as KEPT=LINKED"))
+}
+
 #[derive(Parser)]
 pub struct Opt {
     pub path_to_gtfs: String,
-    pub route_id: String,
+    #[arg(required = true)]
+    pub route_ids: Vec<String>,
 
     #[arg(short = 'o')]
     pub out: Option<std::path::PathBuf>,
+
+    /// Format of the timetable file, binary loads much faster.
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Ron)]
+    pub format: OutputFormat,
+
+    /// Compress binary timetables with zstd.
+    #[arg(long)]
+    pub zstd: bool,
+
+    /// RON file listing school holidays, to tell school days services from holidays ones.
+    #[arg(long)]
+    pub school_holidays: Option<std::path::PathBuf>,
+
+    /// School zone of the network, Île-de-France being in zone C.
+    #[arg(long, default_value = "C")]
+    pub school_zone: String,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Ron,
+    Binary,
+}
+
+impl OutputFormat {
+    pub fn storage_format(self, zstd: bool) -> morningstar_model::storage::Format {
+        match self {
+            OutputFormat::Ron => morningstar_model::storage::Format::Ron,
+            OutputFormat::Binary => morningstar_model::storage::Format::Binary { compressed: zstd },
+        }
+    }
+}
+
+impl Opt {
+    fn storage_format(&self) -> morningstar_model::storage::Format {
+        self.format.storage_format(self.zstd)
+    }
+
+    fn default_out(&self) -> &'static str {
+        match self.format {
+            OutputFormat::Ron => "timetable.ron",
+            OutputFormat::Binary => "timetable.mstt",
+        }
+    }
 }
 
 impl std::fmt::Display for Opt {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "== Morning Star Parsing Options ==")?;
         writeln!(f, "GTFS path: {}", self.path_to_gtfs)?;
-        writeln!(f, "route ID: {}", self.route_id)?;
+        writeln!(f, "route IDs: {}", self.route_ids.join(", "))?;
+        match self.storage_format() {
+            morningstar_model::storage::Format::Ron => writeln!(f, "format: RON")?,
+            morningstar_model::storage::Format::Binary { compressed } => writeln!(
+                f,
+                "format: binary{}",
+                if compressed { ", zstd compressed" } else { "" }
+            )?,
+        }
         match &self.out {
             Some(path) => writeln!(f, "output to file: {}", path.display()),
             None => writeln!(f, "not outputing to file"),
@@ -23,10 +134,49 @@ impl std::fmt::Display for Opt {
     }
 }
 
+/// Changes from the `old` timetable file to the `new` one, as text or JSON.
+pub fn diff_files(
+    old: &std::path::Path,
+    new: &std::path::Path,
+    json: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let old = morningstar_model::TimeTable::load(old)?;
+    let new = morningstar_model::TimeTable::load(new)?;
+    let diff = old.diff(&new);
+    if json {
+        Ok(serde_json::to_string_pretty(&diff)?)
+    } else {
+        Ok(diff.to_string())
+    }
+}
+
+/// Merges timetable files into `out`, namespacing each by its file name.
+pub fn merge_files(
+    timetables: &[std::path::PathBuf],
+    equivalences: &[morningstar_model::merge::StopEquivalence],
+    out: &std::path::Path,
+    format: morningstar_model::storage::Format,
+) -> Result<morningstar_model::TimeTable, Box<dyn std::error::Error>> {
+    let mut namespaces = vec![];
+    let mut loaded = vec![];
+    for path in timetables {
+        let namespace = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .ok_or_else(|| format!("{} has no file name", path.display()))?;
+        namespaces.push(namespace);
+        loaded.push(morningstar_model::TimeTable::load(path)?);
+    }
+    let namespaces = namespaces.iter().map(String::as_str);
+    let tt = morningstar_model::TimeTable::merge(namespaces.zip(loaded), equivalences)?;
+    tt.save(out, format)?;
+    Ok(tt)
+}
+
 pub fn if_file_get_date(fpath: &str) -> Option<chrono::DateTime<Utc>> {
     let fpath = <std::path::PathBuf as std::str::FromStr>::from_str(fpath).ok()?;
     let meta = fpath.metadata().ok()?;
-    let date = chrono::DateTime::try_from(meta.created().ok()?).ok()?;
+    let date = chrono::DateTime::from(meta.created().ok()?);
     Some(date)
 }
 
@@ -34,37 +184,56 @@ pub struct MorningstarPasrer {
     pub spinner: spinoff::Spinner,
 }
 
-impl MorningstarPasrer {
-    pub fn new() -> Self {
+impl Default for MorningstarPasrer {
+    fn default() -> Self {
         Self {
             spinner: spinoff::Spinner::new(spinoff::spinners::Dots, "Parsing", None),
         }
     }
+}
+
+impl MorningstarPasrer {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
     pub fn run_with_opt(
         &mut self,
         opt: &Opt,
     ) -> Result<morningstar_model::TimeTable, Box<dyn std::error::Error>> {
         let gtfs = self.initial_parsing(&opt.path_to_gtfs)?;
-        let tt = {
-            let mut tt = self.extract_route(gtfs, &opt.route_id)?;
+        let mut tt = {
+            let mut tt = self.extract_routes(gtfs, &opt.route_ids)?;
             tt.extracted_from = opt.path_to_gtfs.to_owned();
             if let Some(date) = if_file_get_date(&opt.path_to_gtfs) {
                 tt.extracted_on = date;
             }
-            tt.extracted_line_id = opt.route_id.clone();
+            tt.extracted_route_ids = opt.route_ids.clone();
+            if let Some(path) = &opt.school_holidays {
+                tt.holidays =
+                    morningstar_model::holidays::HolidayCalendar::load(path, &opt.school_zone)?;
+            }
             tt
         };
 
-        self.spinner.update_text("Serialising");
-        let serialized = ron::ser::to_string(&tt)?;
-
-        self.spinner.update_text("Creating file");
-        let mut file =
-            std::fs::File::create(opt.out.as_ref().unwrap_or(&("timetable.ron".into())))?;
+        // Before sorting, which would hide stop times given out of order.
+        self.spinner.update_text("Validating");
+        let validation = tt.validate();
+        if validation.has_errors() {
+            return Err(format!("the extracted timetable is invalid:\n{validation}").into());
+        }
+        if !validation.is_empty() {
+            self.spinner
+                .warn(&format!("{} warnings", validation.findings.len()));
+            eprint!("{validation}");
+            self.spinner =
+                spinoff::Spinner::new(spinoff::spinners::Dots, "Serialising to file", None);
+        }
+        tt.sort_journeys_and_stops();
 
-        self.spinner.update_text("Writing to file");
-        std::io::Write::write(&mut file, serialized.as_bytes())?;
+        self.spinner.update_text("Serialising to file");
+        let out = opt.out.clone().unwrap_or_else(|| opt.default_out().into());
+        tt.save(out, opt.storage_format())?;
         self.spinner.success("All done!");
 
         Ok(tt)
@@ -79,15 +248,79 @@ impl MorningstarPasrer {
         Ok(gtfs)
     }
 
-    fn extract_route(
+    fn extract_routes(
         &mut self,
         gtfs: gtfs_structures::Gtfs,
-        route_id: &str,
+        route_ids: &[String],
     ) -> Result<morningstar_model::TimeTable, Box<dyn std::error::Error>> {
         self.spinner =
             spinoff::Spinner::new(spinoff::spinners::Dots, "Extracting to custom model", None);
         let mut tt = morningstar_model::TimeTable::new();
-        extractor::GtfsExtract::extract_gtfs_route(&mut tt, gtfs, route_id)?;
+        extractor::GtfsExtract::extract_gtfs_routes(&mut tt, gtfs, route_ids)?;
         Ok(tt)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../morningstar_model/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn diff_fixtures() {
+        let (v0, v1) = (fixture("timetable_v0.ron"), fixture("timetable_v1.ron"));
+        let diff = diff_files(&v0, &v1, false).unwrap();
+        assert_eq!(
+            diff,
+            "== 1 added journeys ==\n\
+             + 23:50:00 Église -> Gare (trip IDFM:T2, route IDFM:C02298, service wd1)\n\
+             == 1 removed journeys ==\n\
+             - 23:50:00 Église -> Marché (trip wd1:1, route IDFM:C02298, service wd1)\n\
+             == calendars ==\n\
+             wd1: 89 dates added (2025-03-03 to 2025-07-04), 79 dates removed (2024-09-02 to \
+             2024-12-20)\n\
+             == departures by stop ==\n\
+             Gare: +1 -0 ~0\n\
+             Marché: +1 -1 ~0\n\
+             Église: +1 -1 ~0\n"
+        );
+        assert_eq!(diff_files(&v1, &v1, false).unwrap(), "No changes\n");
+
+        let json: serde_json::Value =
+            serde_json::from_str(&diff_files(&v0, &v1, true).unwrap()).unwrap();
+        assert_eq!(json["calendar_changes"][0]["service_id"], "wd1");
+        assert_eq!(json["added_journeys"][0]["trip_id"], "IDFM:T2");
+    }
+
+    #[test]
+    fn merge_fixtures() {
+        let out = std::env::temp_dir().join(format!("merged_{}.mstt", std::process::id()));
+        let merged = merge_files(
+            &[fixture("timetable_v0.ron"), fixture("timetable_v1.ron")],
+            &[],
+            &out,
+            morningstar_model::storage::Format::Binary { compressed: true },
+        )
+        .unwrap();
+        let reloaded = morningstar_model::TimeTable::load(&out).unwrap();
+        std::fs::remove_file(&out).unwrap();
+        assert!(merged.routes.contains_key("timetable_v0:IDFM:C02298"));
+        assert!(merged.routes.contains_key("timetable_v1:IDFM:C02298"));
+        assert_eq!(reloaded.journeys.len(), merged.journeys.len());
+        assert_eq!(reloaded.stops.len(), 6);
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(
+            parse_link("town:s2=coast:s1"),
+            Ok(("town:s2".to_owned(), "coast:s1".to_owned()))
+        );
+        assert!(parse_link("town:s2").is_err());
+    }
+}
@@ -2,22 +2,87 @@ use clap::Parser;
 use morningstar_parser::*;
 
 fn main() -> std::process::ExitCode {
-    let opt = Opt::parse();
+    let cli = Cli::parse();
+    match (cli.command, cli.opt) {
+        (Some(Command::Diff { old, new, json }), _) => print_or_fail(diff_files(&old, &new, json)),
+        (
+            Some(Command::Merge {
+                timetables,
+                out,
+                same_name_within,
+                link,
+                format,
+                zstd,
+            }),
+            _,
+        ) => {
+            use morningstar_model::merge::StopEquivalence;
+            let equivalences: Vec<_> = same_name_within
+                .map(|max_distance_meters| StopEquivalence::SameName {
+                    max_distance_meters,
+                })
+                .into_iter()
+                .chain(
+                    link.into_iter()
+                        .map(|(kept, linked)| StopEquivalence::Ids(kept.into(), linked.into())),
+                )
+                .collect();
+            let merged = merge_files(
+                &timetables,
+                &equivalences,
+                &out,
+                format.storage_format(zstd),
+            )
+            .map(|tt| {
+                format!(
+                    "Merged {} journeys, {} stops into {}",
+                    tt.journeys.len(),
+                    tt.stops.len(),
+                    out.display()
+                )
+            });
+            print_or_fail(merged)
+        }
+        (None, Some(opt)) => extract(&opt),
+        (None, None) => unreachable!("clap to require extraction arguments without a subcommand"),
+    }
+}
+
+fn print_or_fail(result: Result<String, Box<dyn std::error::Error>>) -> std::process::ExitCode {
+    match result {
+        Ok(output) => {
+            // Text diffs already end their last line.
+            if output.ends_with('\n') {
+                print!("{output}");
+            } else {
+                println!("{output}");
+            }
+            std::process::ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::ExitCode::FAILURE
+        }
+    }
+}
+
+fn extract(opt: &Opt) -> std::process::ExitCode {
     let mut parser = MorningstarPasrer::new();
 
-    match parser.run_with_opt(&opt) {
+    match parser.run_with_opt(opt) {
         Ok(tt) => {
             println!(
-                "Parsed {} journeys, {} patterns, {} excpetions",
+                "Parsed {} journeys, {} stops, {} patterns, {} exceptions",
                 tt.journeys.len(),
+                tt.stops.len(),
                 tt.service_patterns.len(),
-                tt.excpetions.len()
+                tt.exceptions.len()
             );
             std::process::ExitCode::SUCCESS
         }
         Err(err) => {
             parser.spinner.fail(&err.to_string());
-            std::process::ExitCode::SUCCESS
+            std::process::ExitCode::FAILURE
         }
     }
 }
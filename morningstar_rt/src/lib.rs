@@ -1,6 +1,6 @@
 pub mod mock;
 mod prim;
-use chrono::{DateTime, Timelike, Utc, prelude::*};
+use chrono::{DateTime, Timelike, prelude::*};
 pub use prim::{IdfmPrimClient, StopId};
 pub mod parser_invoker;
 pub mod web_api;
@@ -16,16 +16,16 @@ async fn main() -> anyhow::Result<()> {
     let prim_client = morningstar_rt::IdfmPrimClient::new(std::env::var("API_KEY")?);
     let (timetable, file_path) = match opt.file {
         Some(path) => {
-            let file = std::fs::File::open(&path)?;
-            let mut tt: morningstar_model::TimeTable = ron::de::from_reader(file)?;
-            tt.sort_journeys_and_stops();
+            let tt = morningstar_rt::parser_invoker::Invoker::ingest_file_sync(path.clone())?;
             (tt, path)
         }
         None => {
-            let dest = std::path::PathBuf::from_str("./tt.ron").unwrap();
+            let dest = std::path::PathBuf::from_str("./tt.mstt").unwrap();
             let invoker = morningstar_rt::parser_invoker::Invoker {
-                gtfs_source: "https://www.data.gouv.fr/fr/datasets/r/f9fff5b1-f9e4-4ec2-b8b3-8ad7005d869c".to_owned(),
-                route_id: "IDFM:C02298".to_owned(),
+                gtfs_source:
+                    "https://www.data.gouv.fr/fr/datasets/r/f9fff5b1-f9e4-4ec2-b8b3-8ad7005d869c"
+                        .to_owned(),
+                route_ids: vec!["IDFM:C02298".to_owned()],
                 timetable_dest: dest.clone(),
             };
             let tt = invoker.run().await?;
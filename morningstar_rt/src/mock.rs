@@ -1,6 +1,6 @@
 use super::{RealtimeStop, RealtimeStopStatus};
 use chrono::prelude::*;
-use morningstar_model::{StopTime, StopTimeWithDestination};
+use morningstar_model::{PickupDropOff, StopTime, StopTimeWithDestination};
 
 /// Generator to use for testing, that produces realtime and theorical data from a specific date
 /// and time.
@@ -47,18 +47,15 @@ impl FakeGenerator {
     }
 
     /// Generate a theorical stop time using a minute offset from the base date.
-    pub fn create_stop_time(
-        &self,
-        minutes_offset: i64,
-        stop_name: &str,
-        stop_id: &str,
-    ) -> StopTime {
+    pub fn create_stop_time(&self, minutes_offset: i64, stop_id: &str) -> StopTime {
         let time_with_offset = self.base_date + chrono::Duration::minutes(minutes_offset);
 
         StopTime {
-            time: time_with_offset.time(),
-            stop_name: stop_name.to_string(),
-            stop_id: stop_id.to_string(),
+            arrival_time: time_with_offset.time().into(),
+            departure_time: time_with_offset.time().into(),
+            stop_id: stop_id.into(),
+            pickup_type: PickupDropOff::Regular,
+            drop_off_type: PickupDropOff::Regular,
         }
     }
 
@@ -74,10 +71,20 @@ impl FakeGenerator {
 
         StopTimeWithDestination {
             time: time_with_offset.time(),
+            departure: time_with_offset,
+            service_date: time_with_offset.date_naive(),
+            exact_time: true,
             stop_name: stop_name.to_string(),
-            stop_id: stop_id.to_string(),
+            stop_id: stop_id.into(),
+            route_id: "IDFM:C02298".to_string(),
+            route_name: Some("2245".to_string()),
+            trip_id: format!("mock:{minutes_offset}"),
+            direction_id: None,
             destination: destination.to_string(),
             stops_to_destination: 3,
+            variant: morningstar_model::variant::MAIN_VARIANT_LABEL.to_owned(),
+            main_variant: true,
+            holiday: None,
         }
     }
 
@@ -123,18 +130,6 @@ impl FakeGenerator {
         ]
     }
 
-    /// Sample a list of fake theorical stops.
-    fn fake_theorical_list(&self) -> Vec<morningstar_model::StopTime> {
-        vec![
-            self.create_stop_time(-38, "Parc du Bel-Air", "IDFM:123"),
-            self.create_stop_time(-8, "Parc du Bel-Air", "IDFM:123"),
-            self.create_stop_time(0, "Parc du Bel-Air", "IDFM:123"),
-            self.create_stop_time(10, "Parc du Bel-Air", "IDFM:123"),
-            self.create_stop_time(20, "Parc du Bel-Air", "IDFM:123"),
-            self.create_stop_time(40, "Parc du Bel-Air", "IDFM:123"),
-        ]
-    }
-
     /// Sample a list of fake realtime stops.
     pub fn fake_realtime_list(&self) -> Vec<RealtimeStop> {
         vec![
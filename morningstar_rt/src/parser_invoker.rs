@@ -6,21 +6,21 @@ pub enum Error {
     ProcessWait(tokio::io::Error),
     #[error("parser failed, no details available")]
     ParserError,
-    #[error("failed opening timetable file: {_0}")]
-    FileOpening(std::io::Error),
     #[error("failed ingesting timetable file: {_0}")]
-    FileProcessing(ron::de::SpannedError),
+    FileProcessing(morningstar_model::storage::Error),
     #[error("failed to join on the file processing task: {_0}")]
     FileProcessingTask(tokio::task::JoinError),
     #[error("opt must contain a filepath")]
     MissingFilePath,
+    #[error("timetable failed validation:\n{_0}")]
+    Invalid(morningstar_model::validation::Validation),
 }
 
 pub type InvokerResult<T> = Result<T, Error>;
 
 pub struct Invoker {
     pub gtfs_source: String,
-    pub route_id: String,
+    pub route_ids: Vec<String>,
     pub timetable_dest: std::path::PathBuf,
 }
 
@@ -28,7 +28,7 @@ impl std::fmt::Display for Invoker {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "== Parser Invoker Options ==")?;
         writeln!(f, "GTFS source: {}", self.gtfs_source)?;
-        writeln!(f, "route ID: {}", self.route_id)?;
+        writeln!(f, "route IDs: {}", self.route_ids.join(", "))?;
         writeln!(
             f,
             "parsed timetable destination: {}",
@@ -54,21 +54,31 @@ impl Invoker {
         println!("spawning process");
         let child = Command::new(parser_path)
             .arg(self.gtfs_source.clone())
-            .arg(self.route_id.clone())
+            .args(self.route_ids.clone())
             .arg("-o")
             .arg(self.timetable_dest.clone())
+            .args(self.format_args())
             .kill_on_drop(true)
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit())
             .spawn()
-            .map_err(|err| Error::ProcessSpawn(err))?;
+            .map_err(Error::ProcessSpawn)?;
         println!("process spawned");
         Ok(child)
     }
 
+    /// Parser arguments picking the format of the timetable file from its extension: RON for
+    /// `.ron` files, compressed binary otherwise.
+    fn format_args(&self) -> &'static [&'static str] {
+        match self.timetable_dest.extension() {
+            Some(extension) if extension == "ron" => &["--format", "ron"],
+            _ => &["--format", "binary", "--zstd"],
+        }
+    }
+
     async fn await_child(mut child: tokio::process::Child) -> InvokerResult<()> {
         println!("awaiting child");
-        let status = child.wait().await.map_err(|err| Error::ProcessWait(err))?;
+        let status = child.wait().await.map_err(Error::ProcessWait)?;
         println!("child exited");
         if !status.success() {
             Err(Error::ParserError)
@@ -83,14 +93,63 @@ impl Invoker {
     ) -> InvokerResult<morningstar_model::TimeTable> {
         println!("spawning task to deserialise new timetable");
         let task = tokio::task::spawn_blocking(move || Self::ingest_file_sync(file_path));
-        task.await.map_err(|err| Error::FileProcessingTask(err))?
+        task.await.map_err(Error::FileProcessingTask)?
     }
 
-    fn ingest_file_sync(
+    /// Loads a timetable file, refusing it when validation finds errors, then sorts it and builds
+    /// its index.
+    pub fn ingest_file_sync(
         file_path: std::path::PathBuf,
     ) -> InvokerResult<morningstar_model::TimeTable> {
         println!("opening file and deserialising");
-        let file = std::fs::File::open(file_path).map_err(|err| Error::FileOpening(err))?;
-        Ok(ron::de::from_reader(file).map_err(|err| Error::FileProcessing(err))?)
+        let mut timetable =
+            morningstar_model::TimeTable::load(file_path).map_err(Error::FileProcessing)?;
+        let validation = timetable.validate();
+        if validation.has_errors() {
+            return Err(Error::Invalid(validation));
+        }
+        print!("{validation}");
+        timetable.sort_journeys_and_stops();
+        // Built here rather than on the first request once the timetable is swapped in.
+        timetable.build_index();
+        Ok(timetable)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Error, Invoker};
+
+    fn invoker(timetable_dest: &str) -> Invoker {
+        Invoker {
+            gtfs_source: "gtfs.zip".to_owned(),
+            route_ids: vec!["r1".to_owned()],
+            timetable_dest: timetable_dest.into(),
+        }
+    }
+
+    #[test]
+    fn format_follows_destination() {
+        assert_eq!(invoker("./tt.ron").format_args(), ["--format", "ron"]);
+        assert_eq!(
+            invoker("./tt.mstt").format_args(),
+            ["--format", "binary", "--zstd"]
+        );
+    }
+
+    #[test]
+    fn invalid_timetable_is_refused() {
+        let path = std::env::temp_dir().join(format!("invalid_tt_{}.ron", std::process::id()));
+        let mut timetable = morningstar_model::TimeTable::new();
+        timetable.timezone = "Europe/Paris".to_owned();
+        timetable
+            .save(&path, morningstar_model::storage::Format::Ron)
+            .unwrap();
+        let ingested = Invoker::ingest_file_sync(path.clone());
+        std::fs::remove_file(path).unwrap();
+        let Err(Error::Invalid(validation)) = ingested else {
+            panic!("a timetable without journeys was ingested");
+        };
+        assert!(validation.has_errors());
     }
 }
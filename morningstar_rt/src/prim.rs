@@ -1,9 +1,9 @@
 use crate::{RealtimeStop, RealtimeStopStatus};
-use chrono::{DateTime, Timelike, Utc, prelude::*};
+use chrono::{DateTime, Utc};
 
-const PRIM_STOP_ID_PREFIX: &'static str = "STIF:StopPoint:Q:";
-const PRIM_STOP_ID_SUFFIX: &'static str = ":";
-const GTFS_STOP_ID_PREFIX: &'static str = "IDFM:";
+const PRIM_STOP_ID_PREFIX: &str = "STIF:StopPoint:Q:";
+const PRIM_STOP_ID_SUFFIX: &str = ":";
+const GTFS_STOP_ID_PREFIX: &str = "IDFM:";
 
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub struct StopId(String);
@@ -20,45 +20,6 @@ impl std::str::FromStr for StopId {
     }
 }
 
-#[cfg(test)]
-mod test_stop_id {
-    #[test]
-    fn from_gtfs_id_str() {
-        let stop_id: super::StopId = "IDFM:1234".parse().unwrap();
-        assert_eq!(stop_id.prim(), "STIF:StopPoint:Q:1234:");
-        assert_eq!(stop_id.bare(), "1234");
-        assert_eq!(stop_id.gtfs().as_str(), "IDFM:1234");
-    }
-
-    #[test]
-    fn from_bare_str() {
-        let stop_id: super::StopId = "1234".parse().unwrap();
-        assert_eq!(stop_id.prim(), "STIF:StopPoint:Q:1234:");
-        assert_eq!(stop_id.bare(), "1234");
-    }
-
-    #[test]
-    fn from_bare_str_with_suffix() {
-        let stop_id: super::StopId = "1234:".parse().unwrap();
-        assert_eq!(stop_id.prim(), "STIF:StopPoint:Q:1234:");
-        assert_eq!(stop_id.bare(), "1234");
-    }
-
-    #[test]
-    fn from_bare_with_prefix() {
-        let stop_id: super::StopId = "STIF:StopPoint:Q:1234".parse().unwrap();
-        assert_eq!(stop_id.prim(), "STIF:StopPoint:Q:1234:");
-        assert_eq!(stop_id.bare(), "1234");
-    }
-
-    #[test]
-    fn from_bare_with_both_affixes() {
-        let stop_id: super::StopId = "STIF:StopPoint:Q:1234:".parse().unwrap();
-        assert_eq!(stop_id.prim(), "STIF:StopPoint:Q:1234:");
-        assert_eq!(stop_id.bare(), "1234");
-    }
-}
-
 impl std::fmt::Display for StopId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
@@ -86,15 +47,16 @@ impl<'a> StopId {
     }
 }
 
+/// Cached stop monitoring responses, with the date they were fetched on.
+type StopMonitoringCache = std::collections::HashMap<StopId, (DateTime<Utc>, Vec<RealtimeStop>)>;
+
 /// Client for https://prim.iledefrance-mobilites.fr, on which you need an account to get an
 /// apikey.
 pub struct IdfmPrimClient {
     api_key: String,
     api_base_url: &'static str,
     api_client: reqwest::Client,
-    stop_monitoring_cache: std::sync::RwLock<
-        std::collections::HashMap<StopId, (chrono::DateTime<Utc>, Vec<RealtimeStop>)>,
-    >,
+    stop_monitoring_cache: std::sync::RwLock<StopMonitoringCache>,
 }
 
 impl IdfmPrimClient {
@@ -220,3 +182,42 @@ pub fn parse_bus_info(json_value: serde_json::Value) -> anyhow::Result<Vec<Realt
     }
     Ok(results)
 }
+
+#[cfg(test)]
+mod test_stop_id {
+    #[test]
+    fn from_gtfs_id_str() {
+        let stop_id: super::StopId = "IDFM:1234".parse().unwrap();
+        assert_eq!(stop_id.prim(), "STIF:StopPoint:Q:1234:");
+        assert_eq!(stop_id.bare(), "1234");
+        assert_eq!(stop_id.gtfs().as_str(), "IDFM:1234");
+    }
+
+    #[test]
+    fn from_bare_str() {
+        let stop_id: super::StopId = "1234".parse().unwrap();
+        assert_eq!(stop_id.prim(), "STIF:StopPoint:Q:1234:");
+        assert_eq!(stop_id.bare(), "1234");
+    }
+
+    #[test]
+    fn from_bare_str_with_suffix() {
+        let stop_id: super::StopId = "1234:".parse().unwrap();
+        assert_eq!(stop_id.prim(), "STIF:StopPoint:Q:1234:");
+        assert_eq!(stop_id.bare(), "1234");
+    }
+
+    #[test]
+    fn from_bare_with_prefix() {
+        let stop_id: super::StopId = "STIF:StopPoint:Q:1234".parse().unwrap();
+        assert_eq!(stop_id.prim(), "STIF:StopPoint:Q:1234:");
+        assert_eq!(stop_id.bare(), "1234");
+    }
+
+    #[test]
+    fn from_bare_with_both_affixes() {
+        let stop_id: super::StopId = "STIF:StopPoint:Q:1234:".parse().unwrap();
+        assert_eq!(stop_id.prim(), "STIF:StopPoint:Q:1234:");
+        assert_eq!(stop_id.bare(), "1234");
+    }
+}
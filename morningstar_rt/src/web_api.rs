@@ -1,4 +1,4 @@
 mod poem;
 mod state;
 pub use poem::web_server;
-pub use state::{MorningstarState, StopTimeDto, timetable_update_on_expiry};
+pub use state::{MorningstarState, QuayDto, StopTimeDto, timetable_update_on_expiry};
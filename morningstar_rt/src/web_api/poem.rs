@@ -1,7 +1,15 @@
 use super::MorningstarState;
-use super::StopTimeDto;
+use super::{QuayDto, StopTimeDto};
 use chrono::prelude::*;
-use poem::web::{Data, Html, Json, Path};
+use morningstar_model::{DepartureFilter, Direction, StopId, StopSelector};
+use poem::IntoResponse;
+use poem::web::{Data, Html, Json, Path, Query};
+
+/// Days a calendar feed covers unless asked otherwise.
+const CALENDAR_DAYS: u64 = 14;
+
+/// Stops a search returns unless asked otherwise.
+const SEARCH_LIMIT: usize = 10;
 
 #[poem::handler]
 fn index() -> Html<&'static str> {
@@ -14,31 +22,216 @@ async fn served_stops(Data(state): Data<&std::sync::Arc<MorningstarState>>) -> J
     let timetable = state.timetable.read().await;
     Json(
         timetable
-            .get_stops_served_on_day(&today)
+            .get_stops_served_on_day(&today, None)
             .iter()
             .map(|val| val.to_string())
             .collect(),
     )
 }
 
+#[derive(serde::Deserialize)]
+struct SearchQuery {
+    q: String,
+    limit: Option<usize>,
+}
+
+/// Stop names matching what was typed, best first, with their score.
+#[poem::handler]
+async fn hdl_search(
+    Data(state): Data<&std::sync::Arc<MorningstarState>>,
+    Query(query): Query<SearchQuery>,
+) -> Json<Vec<morningstar_model::search::StopMatch>> {
+    let timetable = state.timetable.read().await;
+    Json(timetable.search_stops(&query.q, query.limit.unwrap_or(SEARCH_LIMIT)))
+}
+
+#[derive(serde::Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum DirectionParam {
+    Outbound,
+    Inbound,
+}
+
+impl From<DirectionParam> for Direction {
+    fn from(direction: DirectionParam) -> Self {
+        match direction {
+            DirectionParam::Outbound => Self::Outbound,
+            DirectionParam::Inbound => Self::Inbound,
+        }
+    }
+}
+
+/// Departures to keep, for stops served both ways.
+#[derive(serde::Deserialize)]
+struct FilterQuery {
+    /// Only departures going this way along the line.
+    direction: Option<DirectionParam>,
+    /// Only departures ending at this stop or showing this destination.
+    towards: Option<String>,
+    /// Only departures calling at this stop later on, wherever they end.
+    via: Option<String>,
+}
+
+impl FilterQuery {
+    /// Same filter with the `via` stop name resolved, `None` when no stop matches it.
+    async fn resolve(mut self, state: &MorningstarState) -> Option<Self> {
+        if let Some(via) = self.via {
+            self.via = Some(state.find_stop_name(&via).await?);
+        }
+        Some(self)
+    }
+
+    fn filter(&self) -> DepartureFilter<'_> {
+        DepartureFilter {
+            direction: self.direction.map(Into::into),
+            terminus: self.towards.as_deref(),
+            calls_at: self.via.as_ref().map(StopSelector::from),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct StopTimesQuery {
+    /// Only departures from this quay of the stop, by stop id.
+    quay: Option<String>,
+    #[serde(flatten)]
+    filter: FilterQuery,
+}
+
+/// Next departures from every quay with the stop's name, or from one of them.
 #[poem::handler]
 async fn hdl_stoptimes(
     Data(state): Data<&std::sync::Arc<MorningstarState>>,
     Path(stop_name): Path<String>,
+    Query(query): Query<StopTimesQuery>,
 ) -> Json<Vec<StopTimeDto>> {
-    let stoptimes = state.next_stops_a(&stop_name).await;
+    let Some(stop_name) = state.find_stop_name(&stop_name).await else {
+        return Json(vec![]);
+    };
+    let Some(filter) = query.filter.resolve(state).await else {
+        return Json(vec![]);
+    };
+    let filter = filter.filter();
+    let stoptimes = match query.quay {
+        Some(quay) => {
+            let quay = StopId::from(quay);
+            let is_named = state
+                .quays(&stop_name)
+                .await
+                .iter()
+                .any(|found| found.stop_id == quay.as_str());
+            if !is_named {
+                return Json(vec![]);
+            }
+            state.next_stops(StopSelector::Id(&quay), filter).await
+        }
+        None => {
+            state
+                .next_stops(StopSelector::Name(&stop_name), filter)
+                .await
+        }
+    };
     Json(stoptimes)
 }
 
-pub async fn web_server(state: std::sync::Arc<MorningstarState>) -> anyhow::Result<()> {
-    use poem::{
-        EndpointExt, Route, Server, get, http::Method, listener::TcpListener, middleware::Cors,
+/// Quays with the stop's name, to pick one side of the road.
+#[poem::handler]
+async fn hdl_quays(
+    Data(state): Data<&std::sync::Arc<MorningstarState>>,
+    Path(stop_name): Path<String>,
+) -> Json<Vec<QuayDto>> {
+    let Some(stop_name) = state.find_stop_name(&stop_name).await else {
+        return Json(vec![]);
     };
+    Json(state.quays(&stop_name).await)
+}
+
+/// Next departures from the quays of a stop area, by its stop id.
+#[poem::handler]
+async fn hdl_area_stoptimes(
+    Data(state): Data<&std::sync::Arc<MorningstarState>>,
+    Path(area_id): Path<String>,
+    Query(query): Query<FilterQuery>,
+) -> Json<Vec<StopTimeDto>> {
+    let area_id = StopId::from(area_id);
+    let Some(filter) = query.resolve(state).await else {
+        return Json(vec![]);
+    };
+    Json(
+        state
+            .next_stops(StopSelector::Area(&area_id), filter.filter())
+            .await,
+    )
+}
+
+#[derive(serde::Deserialize)]
+struct CalendarQuery {
+    /// Only trips going on to this stop.
+    to: Option<String>,
+    days: Option<u64>,
+}
+
+/// Trips from the stop as a calendar to subscribe to, starting today.
+#[poem::handler]
+async fn hdl_calendar(
+    Data(state): Data<&std::sync::Arc<MorningstarState>>,
+    Path(stop_name): Path<String>,
+    Query(query): Query<CalendarQuery>,
+) -> impl IntoResponse {
+    let timetable = state.timetable.read().await;
+    let stop_name = timetable.find_stop_name(&stop_name).unwrap_or(stop_name);
+    let to = query
+        .to
+        .map(|to| timetable.find_stop_name(&to).unwrap_or(to));
+    let start = Utc::now().with_timezone(&timetable.tz()).date_naive();
+    let days = query.days.unwrap_or(CALENDAR_DAYS).clamp(1, 366);
+    let end = start + chrono::Days::new(days - 1);
+    timetable
+        .get_icalendar(
+            &stop_name,
+            to.as_ref().map(StopSelector::from),
+            start,
+            end,
+            None,
+        )
+        .with_content_type("text/calendar; charset=utf-8")
+}
+
+#[derive(serde::Deserialize)]
+struct StatsQuery {
+    /// Day as YYYY-MM-DD in the week to take figures from, this week when left out.
+    week_of: Option<NaiveDate>,
+}
+
+/// First and last departures, departures per hour and longest gap at the stop, by type of day.
+#[poem::handler]
+async fn hdl_stats(
+    Data(state): Data<&std::sync::Arc<MorningstarState>>,
+    Path(stop_name): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Json<Vec<morningstar_model::stats::StopStats>> {
+    let timetable = state.timetable.read().await;
+    let Some(stop_name) = timetable.find_stop_name(&stop_name) else {
+        return Json(vec![]);
+    };
+    let week_of = query
+        .week_of
+        .unwrap_or_else(|| Utc::now().with_timezone(&timetable.tz()).date_naive());
+    Json(timetable.get_stop_stats(&stop_name, None, week_of))
+}
+
+pub async fn web_server(state: std::sync::Arc<MorningstarState>) -> anyhow::Result<()> {
+    use poem::{EndpointExt, Route, Server, get, listener::TcpListener, middleware::Cors};
     let cors = Cors::new();
     let routes = Route::new()
         .at("/", get(index))
         .at("/served_today", get(served_stops))
         .at("/stop/:name", get(hdl_stoptimes))
+        .at("/stop/:name/calendar.ics", get(hdl_calendar))
+        .at("/stop/:name/quays", get(hdl_quays))
+        .at("/stop/:name/stats", get(hdl_stats))
+        .at("/area/:id", get(hdl_area_stoptimes))
+        .at("/stops/search", get(hdl_search))
         .with(cors)
         .data(state);
     Ok(Server::new(TcpListener::bind("0.0.0.0:3000"))
@@ -1,39 +1,23 @@
 use crate::{IdfmPrimClient, RealtimeStop, mock};
 use chrono::prelude::*;
-use morningstar_model::{StopTimeWithDestination, TimeTable};
+use morningstar_model::{
+    DepartureFilter, StopId, StopSelector, StopTimeWithDestination, TimeTable,
+};
+use std::collections::HashMap;
 
-/// Makes `chrono::DateTime` from chrono::NaiveTime re-using a common timezone and basedate. We
-/// need it for mass-producing absolute bus stoptimes that can be compared to the realtime date
-/// returns by the IDFM-PRIM Siri-lite data.
-struct DatetimeMaker {
-    tz: chrono_tz::Tz,
-    base_date: chrono::DateTime<FixedOffset>,
-}
+/// Number of departures returned for a stop, across as many days as needed.
+const NEXT_STOPS_COUNT: usize = 20;
 
-impl DatetimeMaker {
-    /// Create a DatetimeMaker
-    fn new(tz_name: &str, base_date: chrono::DateTime<FixedOffset>) -> Option<Self> {
-        let tz = chrono_tz::TZ_VARIANTS
-            .iter()
-            .find(|tz| tz.name() == tz_name)?
-            .clone();
-        Some(Self { tz, base_date })
-    }
+/// Departures looked at to tell where buses leaving from a quay go.
+const QUAY_DESTINATIONS_DEPARTURES: usize = 10;
 
-    /// Generate a `chrono::DateTime` using the `chrono::NaiveTime` provided and the contained
-    /// timezone and base date.
-    fn make_datetime_with_time_and_tz(
-        &self,
-        time: NaiveTime,
-    ) -> Option<chrono::DateTime<FixedOffset>> {
-        let date = self.tz.from_utc_datetime(&self.base_date.naive_utc());
-        use chrono::LocalResult;
-        match date.with_time(time) {
-            LocalResult::None => None,
-            LocalResult::Single(val) => Some(val.fixed_offset()),
-            LocalResult::Ambiguous(earliest, _) => Some(earliest.fixed_offset()),
-        }
-    }
+/// DTO for a quay, to tell the quays sharing a stop name apart.
+#[derive(Debug, serde::Serialize)]
+pub struct QuayDto {
+    pub stop_id: String,
+    pub platform_code: Option<String>,
+    /// Where the next buses leaving from the quay go.
+    pub destinations: Vec<String>,
 }
 
 /// DTO for stop times, merging theorical data and realtime data when it is available.
@@ -45,64 +29,61 @@ pub struct StopTimeDto {
     /// Theorical call time from GTFS.
     pub aimed_arrival: chrono::DateTime<FixedOffset>,
 
+    /// Quay the bus leaves from, from GTFS, there can be one on each side of the road.
+    pub stop_id: Option<String>,
+
     /// Destination (usually generated from Siri)
     pub destination: Option<String>,
 
+    /// Name of the line serving the stop, from GTFS.
+    pub route_name: Option<String>,
+
     /// Number of stops between this stop and destination.
     pub stops_to_destination: Option<u32>,
 
+    /// Stop pattern of the journey when it isn't the line's main one, such as "via Potato
+    /// Factory".
+    pub variant: Option<String>,
+
+    /// Public holiday or school holidays the departure falls on, from GTFS service dates and
+    /// the holiday calendar.
+    pub holiday: Option<String>,
+
     /// Real-time status from Siri.
     pub status: Option<String>,
 }
 
 impl StopTimeDto {
-    /// Make a `StopTimeDto` from theorical and realtime data (when avail.) using a `DatetimeMaker`
-    /// for absolute call datetimes.
-    fn new_with_rt_destination(
-        rt: Option<&crate::RealtimeStop>,
-        theorical_arrival: DateTime<FixedOffset>,
-    ) -> Self {
-        if let Some(rt) = rt {
-            Self {
-                expected_arrival: Some(rt.expected_arrival),
-                aimed_arrival: rt.aimed_arrival,
-                destination: Some(rt.destination.clone()),
-                status: Some(rt.status.to_string()),
-                stops_to_destination: None,
-            }
-        } else {
-            Self {
-                expected_arrival: None,
-                aimed_arrival: theorical_arrival,
-                destination: None,
-                status: None,
-                stops_to_destination: None,
-            }
-        }
-    }
-
-    /// Make a `StopTimeDto` from theorical and realtime data (when avail.) using a `DatetimeMaker`
-    /// for absolute call datetimes.
+    /// Make a `StopTimeDto` from theorical and realtime data (when avail.).
     fn new_with_theorical_destination(
         theorical: &StopTimeWithDestination,
         rt: Option<&crate::RealtimeStop>,
         theorical_arrival: DateTime<FixedOffset>,
     ) -> Self {
+        let variant = Some(theorical.variant.clone()).filter(|_| !theorical.main_variant);
         if let Some(rt) = rt {
             Self {
                 expected_arrival: Some(rt.expected_arrival),
                 aimed_arrival: rt.aimed_arrival,
+                stop_id: Some(theorical.stop_id.to_string()),
                 destination: Some(theorical.destination.clone()),
+                route_name: theorical.route_name.clone(),
                 status: Some(rt.status.to_string()),
                 stops_to_destination: Some(theorical.stops_to_destination),
+                variant,
+                holiday: theorical.holiday.clone(),
             }
         } else {
             Self {
                 expected_arrival: None,
                 aimed_arrival: theorical_arrival,
+                stop_id: Some(theorical.stop_id.to_string()),
                 destination: Some(theorical.destination.clone()),
+                route_name: theorical.route_name.clone(),
                 status: None,
                 stops_to_destination: Some(theorical.stops_to_destination),
+                variant,
+                holiday: theorical.holiday.clone(),
             }
         }
     }
@@ -115,9 +96,18 @@ impl std::fmt::Display for StopTimeDto {
             .expected_arrival
             .map(|val| val.to_utc().with_timezone(&Local));
         write!(f, "{:02}:{:02}", aimed.hour(), aimed.minute())?;
+        if let Some(route_name) = &self.route_name {
+            write!(f, " [{}]", route_name)?;
+        }
         if let Some(destination) = &self.destination {
             write!(f, " to {}", destination)?;
         }
+        if let Some(variant) = &self.variant {
+            write!(f, " ({})", variant)?;
+        }
+        if let Some(holiday) = &self.holiday {
+            write!(f, " (holiday service, {})", holiday)?;
+        }
         if let Some(stops) = &self.stops_to_destination {
             write!(f, " in {} stops", stops)?;
         }
@@ -141,15 +131,11 @@ use tokio::sync::RwLock;
 pub struct MorningstarState {
     pub timetable: RwLock<TimeTable>,
     pub prim_client: IdfmPrimClient,
-    dt_maker: DatetimeMaker,
 }
 
 impl MorningstarState {
     pub fn new(timetable: TimeTable, prim_client: IdfmPrimClient) -> Self {
-        let dt_maker =
-            DatetimeMaker::new(timetable.timezone.as_str(), Utc::now().fixed_offset()).unwrap();
         Self {
-            dt_maker,
             prim_client,
             timetable: RwLock::new(timetable),
         }
@@ -162,44 +148,100 @@ impl MorningstarState {
         stoptimes_realtime
             .iter_mut()
             .for_each(|item| item.set_to_localtime());
+        // Fake realtime data isn't tied to a quay.
+        let stoptimes_realtime = stoptimes_theorical
+            .iter()
+            .map(|stop| (stop.stop_id.clone(), stoptimes_realtime.clone()))
+            .collect();
         let dtos = self.mk_stoptime_dto_vec(&stoptimes_realtime, &stoptimes_theorical);
         dtos.iter().for_each(|dto| println!("{dto}"));
     }
 
-    pub async fn next_stops_a(&self, stop_name: &str) -> Vec<StopTimeDto> {
-        let today = chrono::Local::now().naive_local().date();
+    /// Name of the stop a name given by a user most likely means, exact names coming first.
+    pub async fn find_stop_name(&self, stop_name: &str) -> Option<String> {
+        self.timetable.read().await.find_stop_name(stop_name)
+    }
+
+    /// Quays with provided name, which are usually on both sides of the road.
+    pub async fn quays(&self, stop_name: &str) -> Vec<QuayDto> {
+        let timetable = self.timetable.read().await;
+        timetable
+            .get_quays(stop_name)
+            .into_iter()
+            .map(|quay| {
+                let mut destinations = vec![];
+                for departure in timetable
+                    .get_next_departures_from_stop(
+                        &Utc::now(),
+                        &quay.id,
+                        None,
+                        DepartureFilter::default(),
+                    )
+                    .take(QUAY_DESTINATIONS_DEPARTURES)
+                {
+                    if !destinations.contains(&departure.destination) {
+                        destinations.push(departure.destination);
+                    }
+                }
+                QuayDto {
+                    stop_id: quay.id.to_string(),
+                    platform_code: quay.platform_code.clone(),
+                    destinations,
+                }
+            })
+            .collect()
+    }
+
+    /// Next departures from the selected stops that the filter keeps, with realtime data of
+    /// each quay they leave from.
+    pub async fn next_stops(
+        &self,
+        stops: StopSelector<'_>,
+        filter: DepartureFilter<'_>,
+    ) -> Vec<StopTimeDto> {
         let stoptimes_theorical: Vec<_> = {
             let timetable = self.timetable.read().await;
             timetable
-                .get_day_stoptimes_and_destination_for_stop(&today, stop_name)
+                .get_next_departures_from_stop(&Utc::now(), stops, None, filter)
+                .take(NEXT_STOPS_COUNT)
                 .collect()
         };
-        let stop_id = stoptimes_theorical.last().unwrap().stop_id.as_str();
-        let mut stoptimes_realtime = self.prim_client.get_next_busses(stop_id).await.unwrap();
-        stoptimes_realtime
-            .iter_mut()
-            .for_each(|item| item.set_to_localtime());
+        let mut stoptimes_realtime: HashMap<StopId, Vec<RealtimeStop>> = HashMap::new();
+        for stoptime in &stoptimes_theorical {
+            if stoptimes_realtime.contains_key(&stoptime.stop_id) {
+                continue;
+            }
+            let mut realtime = match self
+                .prim_client
+                .get_next_busses(stoptime.stop_id.as_str())
+                .await
+            {
+                Ok(realtime) => realtime,
+                Err(err) => {
+                    println!("no realtime data for {}: {err}", stoptime.stop_id);
+                    vec![]
+                }
+            };
+            realtime.iter_mut().for_each(|item| item.set_to_localtime());
+            stoptimes_realtime.insert(stoptime.stop_id.clone(), realtime);
+        }
         let dtos = self.mk_stoptime_dto_vec(&stoptimes_realtime, &stoptimes_theorical);
         dtos.iter().for_each(|dto| println!("{dto}"));
-        return dtos;
+        dtos
     }
 
     fn mk_stoptime_dto_vec(
         &self,
-        stoptimes_realtime: &[RealtimeStop],
+        stoptimes_realtime: &HashMap<StopId, Vec<RealtimeStop>>,
         stoptimes_theorical: &[StopTimeWithDestination],
     ) -> Vec<StopTimeDto> {
         let mut dtos = vec![];
         for stoptime in stoptimes_theorical {
-            let Some(time) = self.dt_maker.make_datetime_with_time_and_tz(stoptime.time) else {
-                eprintln!(
-                    "stop time {} doesn't exist in destination timezone.",
-                    stoptime.time
-                );
-                continue;
-            };
+            let time = stoptime.departure;
             let stoptime_rt_opt = stoptimes_realtime
-                .iter()
+                .get(&stoptime.stop_id)
+                .into_iter()
+                .flatten()
                 .find(|realtime_stop| realtime_stop.aimed_arrival == time);
             dtos.push(StopTimeDto::new_with_theorical_destination(
                 stoptime,
@@ -218,25 +260,41 @@ pub async fn timetable_update_on_expiry(
     use chrono::Duration as ChronoDuration;
     let deadline_duration = ChronoDuration::days(7);
     loop {
-        let (mut extracted_on, extracted_line_id, extracted_from) = {
+        let (mut extracted_on, extracted_route_ids, extracted_from, expires_on) = {
             let timetable = state.timetable.read().await;
             (
-                timetable.extracted_on.clone(),
-                timetable.extracted_line_id.clone(),
+                timetable.extracted_on,
+                timetable.extracted_route_ids.clone(),
                 timetable.extracted_from.clone(),
+                timetable.expires_within(
+                    Utc::now().with_timezone(&timetable.tz()).date_naive(),
+                    morningstar_model::EXPIRY_WARNING_DAYS,
+                ),
             )
         };
+        if let Some(expires_on) = expires_on {
+            println!(
+                "WARNING: the timetable has no service after {expires_on}, the GTFS source may be outdated"
+            );
+        }
         if Utc::now() >= extracted_on + deadline_duration {
             let parser_invoker = crate::parser_invoker::Invoker {
                 gtfs_source: extracted_from,
-                route_id: extracted_line_id,
+                route_ids: extracted_route_ids,
                 timetable_dest: file_path.to_path_buf(),
             };
             println!("STARTING PARSING (i will eat a lot of your ram am sorry (,,>﹏<,,))");
             println!("{}", parser_invoker);
-            if let Ok(val) = parser_invoker.run().await {
-                extracted_on = val.extracted_on;
-                *state.timetable.write().await = val;
+            match parser_invoker.run().await {
+                Ok(mut val) => {
+                    extracted_on = val.extracted_on;
+                    // The parser is only given the feed, school holidays carry over.
+                    if val.holidays.is_empty() {
+                        val.holidays = state.timetable.read().await.holidays.clone();
+                    }
+                    *state.timetable.write().await = val;
+                }
+                Err(err) => println!("ERROR: {err}\nkeeping the current timetable"),
             }
         }
         let deadline = extracted_on + deadline_duration;
@@ -253,21 +311,6 @@ pub async fn timetable_update_on_expiry(
     }
 }
 
-/// Makes an monotonic Instant in order to wait for a deadline that is `duration` after `base_date`.
-/// That instant can be used with `tokio::time::sleep_until` to wait for that deadline.
-fn mk_deadline_instant_in_days(
-    base_date: DateTime<Utc>,
-    duration: chrono::Duration,
-) -> tokio::time::Instant {
-    use tokio::time::Duration;
-    let deadline = base_date + duration;
-    let now = Utc::now();
-    let remaining = (deadline - now)
-        .to_std()
-        .unwrap_or_else(|_| Duration::from_secs(0));
-    tokio::time::Instant::now() + remaining
-}
-
 fn mk_instant_for_deadline(deadline: DateTime<Utc>) -> tokio::time::Instant {
     use tokio::time::Duration;
     let now = Utc::now();